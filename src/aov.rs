@@ -0,0 +1,217 @@
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::hdr::HdrImage;
+use crate::shared::{Color, splitmix64};
+use crate::tonemap::ToneMap;
+
+/// Auxiliary output, describing the first hit of each pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Aov {
+    // Distance from the camera to the nearest hit, zero where nothing was hit
+    #[serde(rename = "depth")]
+    Depth,
+    // World space shading normal
+    #[serde(rename = "normal")]
+    Normal,
+    // Reflectance of the surface
+    #[serde(rename = "albedo")]
+    Albedo,
+    // Index of the object in the scene, -1 where nothing was hit
+    #[serde(rename = "object-id")]
+    ObjectId,
+    // Number of samples taken, which varies with adaptive sampling
+    #[serde(rename = "sample-count")]
+    SampleCount,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::ObjectId, Aov::SampleCount];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object-id",
+            Aov::SampleCount => "sample-count",
+        }
+    }
+
+    /// Convert to a displayable 8-bit image. Depth is shown as brightness from near to far, normals
+    /// are mapped from [-1, 1], objects get a random colour each and sample counts are shown as a
+    /// heatmap from blue (fewest) to red (most).
+    pub fn visualize(self, img: &HdrImage) -> image::RgbImage {
+        let mut ldr = image::RgbImage::new(img.width(), img.height());
+        match self {
+            Aov::Depth => {
+                let far = img.pixels().map(|p| p[0]).fold(0.0, f32::max);
+                for (x, y, pixel) in img.enumerate_pixels() {
+                    let v = if pixel[0] > 0.0 && far > 0.0 { 1.0 - pixel[0] / far } else { 0.0 };
+                    ldr.put_pixel(x, y, image::Rgb([(255.0 * v) as u8; 3]));
+                }
+            },
+            Aov::Normal => {
+                for (x, y, pixel) in img.enumerate_pixels() {
+                    let channel = |v: f32| (255.0 * (0.5 * v + 0.5)) as u8;
+                    ldr.put_pixel(x, y, image::Rgb([channel(pixel[0]), channel(pixel[1]), channel(pixel[2])]));
+                }
+            },
+            Aov::Albedo => ldr = ToneMap::default().apply(img),
+            Aov::ObjectId => {
+                for (x, y, pixel) in img.enumerate_pixels() {
+                    let colour = if pixel[0] < 0.0 {
+                        [0; 3]
+                    } else {
+                        let hash = splitmix64(pixel[0] as u64);
+                        [hash as u8, (hash >> 8) as u8, (hash >> 16) as u8]
+                    };
+                    ldr.put_pixel(x, y, image::Rgb(colour));
+                }
+            },
+            Aov::SampleCount => {
+                let (min, max) = img.pixels().map(|p| p[0]).fold((f32::INFINITY, 0f32), |(min, max), n| (min.min(n), max.max(n)));
+                for (x, y, pixel) in img.enumerate_pixels() {
+                    let t = if max > min { (pixel[0] - min) / (max - min) } else { 0.0 };
+                    // Blue through green to red
+                    let colour = if t < 0.5 {
+                        Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+                    } else {
+                        Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+                    };
+                    ldr.put_pixel(x, y, image::Rgb([(255.0 * colour.x) as u8, (255.0 * colour.y) as u8, (255.0 * colour.z) as u8]));
+                }
+            },
+        }
+        ldr
+    }
+
+    /// Combine a pixel's value from a further pass over it, where weight is the pass's share of all
+    /// the samples so far
+    pub fn accumulate(self, value: [f32; 3], pass_value: [f32; 3], weight: f32) -> [f32; 3] {
+        match self {
+            // Either pass may have missed, leaving zero
+            Aov::Depth => {
+                let depth = if value[0] > 0.0 && pass_value[0] > 0.0 { value[0].min(pass_value[0]) } else { value[0].max(pass_value[0]) };
+                [depth; 3]
+            },
+            Aov::Normal | Aov::Albedo => {
+                let mut out = value;
+                for c in 0..3 {
+                    out[c] += (pass_value[c] - value[c]) * weight;
+                }
+                out
+            },
+            // The id comes from the first sample, which is in the first pass
+            Aov::ObjectId => value,
+            Aov::SampleCount => [value[0] + pass_value[0]; 3],
+        }
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == s)
+            .ok_or_else(|| format!("unknown AOV '{}', expected one of depth, normal, albedo, object-id, sample-count", s))
+    }
+}
+
+/// Per pixel accumulator for the AOVs
+pub struct AovSample {
+    pub depth: f32,
+    pub normal: Color,
+    pub albedo: Color,
+    pub object_id: f32,
+    pub sample_count: f32,
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        AovSample {
+            depth: 0.0,
+            normal: Color::ZERO,
+            albedo: Color::ZERO,
+            object_id: -1.0,
+            sample_count: 0.0,
+        }
+    }
+}
+
+impl AovSample {
+    pub fn value(&self, aov: Aov) -> [f32; 3] {
+        match aov {
+            Aov::Depth => [self.depth; 3],
+            Aov::Normal => self.normal.into(),
+            Aov::Albedo => self.albedo.into(),
+            Aov::ObjectId => [self.object_id; 3],
+            Aov::SampleCount => [self.sample_count; 3],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulate_depth() {
+        // The nearest hit is kept, and a pass that missed doesn't replace a hit
+        assert_eq!(Aov::Depth.accumulate([3.0; 3], [2.0; 3], 0.5), [2.0; 3]);
+        assert_eq!(Aov::Depth.accumulate([2.0; 3], [3.0; 3], 0.5), [2.0; 3]);
+        assert_eq!(Aov::Depth.accumulate([0.0; 3], [4.0; 3], 0.5), [4.0; 3]);
+        assert_eq!(Aov::Depth.accumulate([4.0; 3], [0.0; 3], 0.5), [4.0; 3]);
+        assert_eq!(Aov::Depth.accumulate([0.0; 3], [0.0; 3], 0.5), [0.0; 3]);
+    }
+
+    #[test]
+    fn accumulate_averages() {
+        // A pass with a quarter of the samples moves the average a quarter of the way
+        for &aov in [Aov::Normal, Aov::Albedo].iter() {
+            assert_eq!(aov.accumulate([0.0, 1.0, 0.5], [1.0, 0.0, 0.5], 0.25), [0.25, 0.75, 0.5]);
+            assert_eq!(aov.accumulate([0.2, 0.4, 0.6], [1.0, 1.0, 1.0], 0.0), [0.2, 0.4, 0.6]);
+        }
+        // Averaging passes weighted by their sample counts gives the mean of all the samples
+        let mut value = [0.0; 3];
+        let mut samples = 0.0;
+        for (pass_value, pass_samples) in [(1.0, 4.0), (0.0, 4.0), (0.5, 8.0)].iter() {
+            samples += pass_samples;
+            value = Aov::Albedo.accumulate(value, [*pass_value; 3], pass_samples / samples);
+        }
+        assert_eq!(value, [0.5; 3]);
+    }
+
+    #[test]
+    fn accumulate_counts_and_ids() {
+        assert_eq!(Aov::SampleCount.accumulate([8.0; 3], [4.0; 3], 1.0 / 3.0), [12.0; 3]);
+        assert_eq!(Aov::ObjectId.accumulate([3.0; 3], [5.0; 3], 0.5), [3.0; 3]);
+        assert_eq!(Aov::ObjectId.accumulate([-1.0; 3], [5.0; 3], 0.5), [-1.0; 3]);
+    }
+
+    #[test]
+    fn names() {
+        for &aov in Aov::ALL.iter() {
+            assert_eq!(aov.to_string().parse::<Aov>(), Ok(aov));
+        }
+        assert!("beauty".parse::<Aov>().is_err());
+    }
+
+    #[test]
+    fn visualize_depth() {
+        let img = HdrImage::from_fn(3, 1, |x, _| image::Rgb([[0.0, 1.0, 2.0][x as usize]; 3]));
+        let ldr = Aov::Depth.visualize(&img);
+        // Misses are black, the near hit brighter than the farthest
+        assert_eq!(ldr.get_pixel(0, 0)[0], 0);
+        assert_eq!(ldr.get_pixel(1, 0)[0], 127);
+        assert_eq!(ldr.get_pixel(2, 0)[0], 0);
+    }
+}
@@ -0,0 +1,358 @@
+//! Precomputed blue noise, which takes too long to make on every worker
+
+// Side of the tileable blue noise texture used to offset samples between pixels
+pub const BLUE_NOISE_SIZE: usize = 64;
+
+/// Rank of each pixel of the texture, row by row. Every rank from 0 to BLUE_NOISE_SIZE^2 - 1
+/// appears once, with pixels of nearby ranks spread apart. Made by `generate` in the tests.
+pub const BLUE_NOISE_RANKS: [u16; BLUE_NOISE_SIZE * BLUE_NOISE_SIZE] = [
+    2455, 1220, 2112, 1649, 3997, 233, 3401, 1796, 1176, 2957, 1464, 2401, 3156, 3560, 2611, 1160,
+    2834, 1322, 1768, 3670, 430, 2462, 1629, 3167, 2074, 3485, 216, 1646, 3968, 2014, 2541, 2911,
+    4093, 3457, 38, 2201, 1579, 2542, 219, 3360, 971, 1673, 310, 3903, 881, 3262, 3817, 2719,
+    3689, 1548, 2865, 152, 845, 1937, 1126, 471, 805, 1378, 120, 2188, 1606, 2940, 837, 3671,
+    2881, 352, 3622, 754, 2871, 2024, 912, 3779, 491, 2011, 774, 3838, 1046, 540, 1677, 3714,
+    73, 2311, 3201, 872, 2160, 3373, 1256, 2689, 589, 1417, 3010, 816, 2717, 1153, 523, 1764,
+    913, 2097, 1395, 3824, 760, 3005, 1795, 2277, 2893, 3742, 2548, 1270, 2863, 2186, 222, 1175,
+    663, 3251, 2130, 3820, 3362, 2739, 3708, 2119, 3300, 3823, 2839, 940, 4050, 1255, 7, 1835,
+    934, 3372, 2676, 1433, 3287, 583, 2526, 3052, 2283, 3539, 2761, 11, 1940, 2961, 2262, 919,
+    1959, 3963, 534, 2963, 1542, 111, 4019, 977, 2239, 3836, 1887, 3525, 316, 3154, 3731, 2442,
+    3241, 382, 2736, 3127, 436, 1294, 3884, 547, 1411, 8, 1908, 3138, 533, 1617, 3509, 1928,
+    2517, 387, 1760, 1032, 542, 1471, 178, 2977, 1181, 1721, 2438, 508, 3359, 2602, 2314, 3910,
+    469, 1295, 2329, 81, 1772, 3842, 1203, 1577, 330, 1111, 1705, 3248, 1401, 4036, 400, 2696,
+    3367, 1578, 1082, 2607, 3611, 1920, 2928, 447, 3291, 61, 1071, 2407, 1559, 2171, 1340, 115,
+    1592, 3601, 1029, 1882, 2422, 3521, 948, 2641, 3236, 777, 3538, 1030, 3966, 2670, 824, 3093,
+    4025, 1379, 3624, 2612, 2210, 4067, 1809, 697, 2259, 229, 3543, 1490, 1976, 385, 3086, 1568,
+    3197, 1962, 4044, 1057, 3023, 2157, 186, 3358, 4014, 2469, 609, 3609, 2539, 831, 3184, 1307,
+    286, 2437, 3506, 206, 1288, 739, 2384, 3680, 1671, 2594, 2953, 4038, 610, 3382, 893, 3934,
+    2033, 2503, 639, 4003, 1506, 94, 2187, 1731, 4088, 2046, 2424, 1545, 329, 2308, 1286, 116,
+    2196, 717, 2934, 12, 3152, 1259, 2547, 3254, 3891, 2667, 1034, 3021, 748, 3659, 1118, 2173,
+    2585, 677, 3456, 2735, 450, 3649, 2622, 1912, 926, 3070, 2077, 1190, 250, 2185, 1639, 3666,
+    2830, 765, 1827, 2203, 3841, 2714, 1494, 1051, 2104, 778, 1336, 268, 1818, 2882, 2590, 453,
+    2969, 1228, 3225, 318, 2753, 3395, 2952, 384, 1150, 3031, 210, 2807, 3381, 1831, 3832, 2778,
+    1662, 3495, 1135, 1971, 762, 3532, 345, 973, 1580, 485, 2079, 3984, 2780, 1751, 183, 3815,
+    1442, 258, 1802, 894, 1625, 1281, 756, 2918, 1447, 117, 3766, 1774, 2931, 3890, 567, 2027,
+    1129, 4004, 3017, 386, 3190, 521, 3393, 172, 3090, 3813, 3258, 2228, 3673, 1045, 1456, 3559,
+    783, 1811, 3746, 2240, 1098, 1973, 793, 3685, 1622, 634, 3604, 1209, 753, 3146, 489, 997,
+    3237, 321, 2439, 3946, 1632, 2858, 2322, 2009, 3652, 3083, 1414, 77, 1193, 2274, 3364, 859,
+    2859, 3712, 2252, 3112, 3898, 2414, 3536, 513, 2257, 3335, 2609, 496, 3454, 1039, 2566, 3337,
+    41, 2377, 1326, 1644, 972, 2061, 4071, 1733, 2657, 360, 1570, 703, 2474, 13, 2094, 3131,
+    2367, 142, 2862, 1605, 532, 3950, 1434, 2300, 3298, 2617, 2115, 1706, 3735, 2176, 1435, 2500,
+    3704, 2076, 1465, 3342, 507, 1097, 3857, 108, 2795, 866, 3451, 2504, 3616, 599, 2659, 1921,
+    441, 3268, 1202, 27, 1984, 361, 3049, 1730, 3926, 1237, 820, 1587, 2295, 195, 1408, 2978,
+    1783, 683, 3432, 3789, 2561, 2905, 1265, 693, 2305, 1121, 1956, 3431, 2929, 3870, 1678, 412,
+    4051, 1394, 935, 3575, 2584, 3109, 133, 2833, 339, 949, 3977, 47, 2589, 397, 3001, 1861,
+    623, 908, 2892, 157, 2645, 1877, 3293, 1327, 607, 1839, 2211, 418, 1637, 3030, 1304, 3998,
+    1675, 2444, 652, 2721, 3375, 1519, 1091, 2683, 300, 2012, 3096, 4085, 2781, 1952, 3843, 880,
+    3636, 2749, 292, 2232, 781, 131, 1895, 3719, 3198, 2823, 3981, 423, 1300, 798, 3289, 1179,
+    2592, 1998, 3353, 256, 1804, 1157, 2069, 3783, 1341, 1874, 3011, 1476, 3487, 1133, 4046, 102,
+    3139, 3850, 1278, 3600, 2199, 788, 2966, 1702, 2549, 3764, 3234, 1070, 3858, 2039, 169, 1018,
+    2092, 3572, 1427, 4066, 827, 2181, 3821, 700, 3608, 2510, 34, 1115, 675, 3229, 393, 2429,
+    1502, 2007, 1169, 3283, 1552, 3068, 3510, 457, 1436, 98, 915, 2205, 1790, 2729, 2302, 626,
+    3646, 2899, 714, 2411, 3818, 574, 3504, 835, 2487, 3369, 577, 2289, 811, 2000, 2725, 1404,
+    2346, 1725, 2516, 556, 1581, 4048, 251, 3409, 961, 319, 1474, 2850, 772, 3490, 2544, 3169,
+    840, 144, 2886, 1777, 2532, 197, 3145, 1884, 1277, 3265, 1544, 2208, 3717, 1717, 1231, 3470,
+    112, 4047, 2949, 429, 3847, 2152, 998, 2537, 2087, 3773, 2604, 3231, 3557, 217, 3831, 1873,
+    80, 1104, 1685, 3164, 1428, 2817, 2316, 1664, 420, 3883, 1184, 2793, 3218, 443, 3619, 701,
+    3429, 263, 965, 3219, 2741, 1178, 2434, 2065, 3887, 2720, 1978, 43, 2335, 1349, 557, 3904,
+    2294, 3113, 1058, 497, 3682, 1346, 2804, 449, 2332, 804, 3522, 2927, 426, 2480, 2857, 2083,
+    624, 2595, 905, 1845, 1239, 2715, 651, 1735, 3417, 1208, 1657, 500, 1073, 1531, 2986, 1313,
+    3217, 2214, 3907, 399, 2026, 969, 36, 3286, 2668, 1953, 180, 1739, 3945, 1572, 2264, 1187,
+    1848, 2864, 3948, 1965, 57, 3703, 699, 1532, 483, 1195, 3062, 4061, 3334, 1820, 2796, 1567,
+    1206, 3814, 1885, 3394, 2306, 887, 3305, 1638, 3801, 2738, 331, 1914, 1355, 823, 3905, 1043,
+    3119, 1555, 3415, 2408, 3573, 9, 4031, 3012, 260, 758, 3077, 2435, 4077, 2145, 850, 2456,
+    3603, 558, 2677, 1279, 3374, 3995, 2989, 1377, 1017, 3596, 3099, 2405, 938, 239, 3285, 2574,
+    3720, 518, 2238, 1363, 3459, 1814, 2853, 3153, 3556, 2413, 1599, 644, 1087, 408, 3727, 253,
+    2555, 633, 2733, 1498, 69, 1991, 4005, 1102, 208, 2084, 1026, 4023, 3026, 3346, 171, 1794,
+    3677, 2246, 200, 680, 2875, 1631, 1290, 2177, 2672, 3846, 2034, 51, 2852, 601, 3332, 249,
+    1600, 950, 3059, 168, 2482, 710, 1844, 3724, 462, 2183, 713, 1410, 3674, 2971, 1960, 346,
+    1015, 1601, 3039, 773, 2498, 381, 987, 2150, 220, 865, 3663, 2207, 2630, 2992, 2031, 3249,
+    3542, 2134, 344, 3930, 3000, 2596, 512, 2426, 3080, 3672, 1526, 2508, 582, 1645, 2344, 2764,
+    448, 1344, 3921, 1114, 2036, 3240, 355, 3599, 1011, 1522, 3351, 1236, 1719, 3578, 1926, 2591,
+    4001, 2125, 1762, 3694, 1488, 2235, 266, 2560, 1607, 2846, 3363, 96, 2614, 1163, 729, 4072,
+    2755, 3365, 162, 3869, 1156, 3252, 4011, 1385, 2681, 3210, 1843, 137, 3440, 1273, 751, 1713,
+    988, 1391, 3214, 842, 1212, 1708, 3561, 1357, 1838, 690, 3399, 54, 2106, 3533, 1267, 712,
+    3296, 1927, 3008, 2578, 3780, 849, 2484, 1815, 591, 2417, 421, 3748, 870, 2700, 1362, 486,
+    1199, 3420, 654, 2774, 963, 3512, 2946, 799, 4057, 1128, 1862, 3899, 1561, 2217, 3530, 1724,
+    2333, 1262, 2019, 2674, 1623, 2319, 627, 1955, 3754, 1122, 572, 3940, 1543, 2458, 3874, 14,
+    2874, 3771, 1822, 2321, 3665, 669, 3263, 124, 2906, 2222, 1242, 2711, 3864, 985, 2913, 3784,
+    2454, 936, 270, 1688, 477, 1444, 3444, 2782, 3988, 3203, 1994, 2939, 2247, 198, 3796, 2896,
+    3189, 20, 2318, 3882, 437, 1942, 1251, 3185, 2105, 338, 2470, 655, 2883, 476, 3188, 23,
+    822, 3798, 561, 3111, 285, 3595, 2820, 30, 1652, 2453, 2960, 2058, 937, 434, 3092, 2256,
+    555, 2501, 177, 2923, 383, 2121, 2710, 970, 3925, 428, 3205, 825, 1569, 396, 1855, 127,
+    1443, 4033, 2126, 3486, 3095, 2301, 163, 1253, 946, 325, 1413, 665, 3284, 1089, 1634, 2200,
+    932, 1868, 1372, 3022, 1655, 2613, 3761, 56, 1481, 3602, 3273, 1062, 3762, 1993, 1330, 2550,
+    2954, 1558, 3423, 994, 2197, 1318, 836, 3044, 3422, 362, 1373, 3312, 2751, 3683, 1922, 1258,
+    3321, 1514, 929, 3404, 1314, 4073, 1901, 1497, 2490, 1689, 3639, 1954, 3064, 2475, 3410, 2191,
+    3118, 575, 2663, 1211, 790, 3947, 1621, 3032, 2219, 3794, 2644, 1765, 4042, 2512, 3468, 635,
+    3951, 2572, 3520, 1100, 232, 3307, 696, 2399, 2798, 862, 1709, 2250, 283, 3446, 909, 3961,
+    2111, 240, 1904, 3699, 2603, 1740, 3845, 2048, 1005, 4086, 749, 2312, 213, 1653, 776, 4039,
+    2656, 2055, 3828, 1736, 2416, 809, 3172, 349, 3496, 722, 2366, 225, 4056, 1291, 744, 3669,
+    1123, 1618, 3580, 32, 2836, 1989, 527, 3551, 1857, 55, 3137, 981, 413, 1934, 128, 1458,
+    2797, 320, 759, 2440, 4021, 2193, 1041, 1821, 3952, 431, 3089, 2627, 1460, 2766, 1793, 380,
+    3303, 1257, 2815, 743, 424, 3199, 262, 2420, 1525, 2704, 1805, 3613, 1180, 3445, 2374, 140,
+    1105, 409, 2991, 588, 2763, 159, 3756, 1076, 2998, 1374, 2806, 1155, 568, 2866, 1701, 2635,
+    351, 2987, 1903, 2383, 1393, 3297, 2506, 853, 2826, 1510, 2361, 3635, 1328, 2842, 3213, 2141,
+    3679, 1770, 3117, 2018, 1466, 514, 3050, 1311, 3380, 2037, 1174, 151, 3919, 630, 3061, 2309,
+    1024, 3785, 2431, 1500, 4027, 1137, 2776, 659, 3705, 166, 3091, 536, 2001, 2948, 1472, 3162,
+    1864, 3481, 2296, 1252, 3513, 1669, 2165, 2639, 1943, 15, 3687, 2198, 3349, 1999, 82, 3939,
+    2080, 867, 3879, 692, 3642, 1065, 241, 3760, 1197, 495, 3356, 741, 2175, 3871, 838, 1141,
+    456, 1305, 3835, 92, 3436, 2702, 3620, 136, 2524, 667, 3745, 3212, 2120, 1268, 3517, 1596,
+    541, 3009, 58, 3387, 2251, 1875, 3548, 1292, 3269, 2122, 1110, 2468, 3800, 911, 458, 3908,
+    715, 1403, 71, 3955, 916, 3055, 1303, 603, 4008, 3161, 1781, 897, 3827, 1486, 3170, 1066,
+    2415, 3330, 1539, 401, 3019, 1749, 2279, 3114, 2017, 3980, 1703, 2933, 234, 1586, 2583, 3528,
+    2326, 2898, 797, 2502, 1131, 1876, 871, 1695, 2943, 1493, 2423, 1775, 828, 2528, 106, 4078,
+    2648, 1757, 2075, 944, 586, 3081, 126, 2343, 1754, 782, 3972, 1453, 37, 2813, 2158, 2571,
+    3647, 2789, 3211, 1995, 2554, 363, 3621, 2418, 956, 1524, 460, 2682, 278, 2481, 718, 3565,
+    1343, 156, 2902, 2050, 2580, 4082, 620, 1470, 2740, 138, 1003, 2540, 3576, 616, 1817, 63,
+    3311, 1938, 1565, 3259, 427, 3734, 2281, 4089, 466, 3502, 1001, 327, 3593, 2903, 2003, 1188,
+    769, 3165, 1280, 3860, 2747, 1609, 1016, 3916, 2895, 373, 2628, 3243, 1771, 3435, 1075, 1628,
+    244, 2216, 1006, 1593, 698, 3313, 1816, 148, 2878, 3550, 2266, 3294, 1283, 3028, 2154, 474,
+    2790, 1798, 3494, 904, 1289, 83, 3407, 922, 3648, 2263, 3224, 1923, 1221, 3045, 4074, 2685,
+    1049, 580, 3923, 2144, 2802, 1421, 203, 3160, 1229, 2147, 2621, 3941, 1446, 524, 3405, 2428,
+    3741, 192, 3447, 2406, 280, 3626, 2531, 551, 1536, 3482, 2215, 1232, 611, 3729, 377, 3126,
+    1830, 4045, 509, 3675, 2255, 2743, 1383, 3763, 1975, 1151, 704, 3986, 1880, 960, 3706, 1665,
+    4020, 2460, 592, 3830, 3186, 2225, 1693, 2546, 444, 1361, 682, 3913, 358, 2313, 789, 1450,
+    3584, 3078, 269, 1245, 708, 3384, 2577, 1972, 720, 2847, 46, 1825, 3106, 1021, 1612, 391,
+    1851, 2827, 1528, 661, 1797, 3278, 1298, 2078, 3747, 966, 223, 3071, 2054, 2684, 2371, 1272,
+    3319, 2586, 1356, 2967, 257, 3889, 902, 515, 2618, 3088, 204, 1574, 2808, 86, 3344, 1233,
+    282, 1002, 2135, 1454, 337, 2845, 1072, 3885, 3079, 1832, 3493, 2485, 1535, 3370, 2006, 189,
+    2233, 1720, 2520, 2947, 3806, 1743, 986, 3591, 1589, 3863, 3368, 884, 2310, 2771, 3987, 3266,
+    847, 2271, 1079, 3924, 2153, 874, 2810, 53, 3150, 2494, 1854, 4064, 895, 1515, 3868, 653,
+    951, 24, 3526, 1970, 1127, 1747, 3200, 2202, 1611, 3469, 2409, 3782, 2117, 631, 2599, 2268,
+    3175, 2888, 3594, 2624, 1846, 3686, 706, 2032, 273, 2799, 933, 25, 2973, 1031, 3810, 2880,
+    1159, 3992, 891, 1961, 21, 2345, 499, 3048, 248, 2070, 1319, 538, 3752, 209, 2137, 1241,
+    3013, 3662, 353, 2557, 3074, 442, 3993, 1786, 689, 1173, 2938, 510, 3413, 154, 2870, 1948,
+    3073, 1663, 2376, 750, 3442, 2425, 104, 4062, 1064, 334, 1320, 876, 2988, 1422, 3953, 1824,
+    752, 1594, 64, 1140, 550, 3324, 2403, 1264, 3546, 1564, 2223, 3786, 1791, 463, 2404, 670,
+    3421, 297, 1503, 3654, 3228, 1371, 3960, 2678, 1092, 2436, 3247, 2718, 1916, 1429, 673, 2623,
+    35, 1981, 3357, 1659, 1223, 3467, 1475, 2368, 3331, 3701, 1415, 2393, 1734, 1125, 2270, 3633,
+    562, 2768, 3927, 296, 2924, 1339, 2688, 766, 2975, 3691, 1915, 3255, 416, 3439, 968, 335,
+    3757, 3272, 1951, 4049, 3029, 1541, 130, 3942, 2499, 608, 3400, 1238, 2643, 3239, 1483, 1905,
+    2660, 3105, 2273, 537, 2552, 791, 2114, 1540, 3664, 440, 1696, 952, 3443, 2999, 3618, 1745,
+    3897, 1382, 614, 2920, 155, 2045, 2680, 1037, 366, 2116, 100, 2772, 3759, 3177, 341, 1366,
+    2095, 1067, 1461, 2174, 3667, 526, 3341, 1690, 2101, 2525, 594, 2691, 1672, 2351, 2049, 2756,
+    1161, 2466, 679, 2170, 2724, 907, 1917, 3136, 991, 2894, 179, 2089, 727, 3878, 125, 3631,
+    1316, 806, 1799, 3463, 1185, 2912, 187, 3437, 709, 2962, 3792, 79, 2337, 406, 1088, 2248,
+    817, 2809, 2387, 4055, 914, 3732, 585, 3570, 2873, 1686, 3965, 942, 579, 1909, 2615, 4030,
+    132, 3579, 3108, 821, 1630, 2043, 1096, 3772, 185, 1412, 3455, 1144, 3914, 2, 3564, 3075,
+    1553, 228, 3497, 1368, 365, 3738, 2237, 479, 1416, 1852, 3990, 3110, 1692, 1117, 2459, 2113,
+    372, 3893, 2754, 107, 4054, 1679, 3204, 1906, 1162, 2172, 2587, 1297, 4068, 1603, 2694, 3222,
+    236, 3515, 1138, 1773, 2184, 3060, 1407, 1944, 807, 3173, 1274, 2212, 3529, 1529, 787, 3271,
+    1779, 2299, 376, 2530, 3888, 60, 2353, 2869, 694, 4000, 2241, 301, 2917, 883, 1360, 546,
+    1982, 3894, 2877, 1008, 3206, 1691, 2606, 3355, 3693, 2342, 888, 417, 2777, 3479, 565, 3069,
+    1009, 3323, 1419, 2108, 917, 2385, 480, 2655, 3943, 271, 1761, 780, 3130, 2067, 569, 3855,
+    1930, 1527, 340, 3299, 482, 2558, 4, 3861, 2432, 293, 3418, 2675, 175, 3041, 2358, 1249,
+    2879, 650, 3390, 1019, 2784, 3484, 1432, 3238, 1823, 989, 3015, 1521, 2479, 1847, 3811, 2570,
+    3333, 813, 1767, 2325, 3967, 48, 800, 1230, 247, 2723, 1602, 3328, 2244, 1402, 4024, 2646,
+    1598, 2402, 632, 3035, 3527, 1293, 3739, 860, 1487, 3223, 3558, 2848, 173, 3658, 955, 1315,
+    2491, 2997, 3681, 2697, 1084, 1616, 3452, 1171, 2819, 1492, 666, 1964, 1116, 3886, 467, 3644,
+    1468, 3971, 2004, 1317, 1840, 564, 885, 2610, 265, 3383, 2008, 481, 3634, 3194, 348, 1048,
+    2195, 121, 2673, 553, 1440, 2921, 3524, 2051, 3054, 597, 3749, 1078, 307, 1900, 855, 1,
+    2022, 3702, 259, 1787, 2633, 332, 2071, 3002, 2293, 498, 1050, 1969, 2392, 1517, 2805, 3474,
+    76, 841, 2047, 638, 3900, 3148, 2260, 548, 1792, 4090, 2297, 3589, 1718, 2737, 2040, 923,
+    33, 2582, 3166, 242, 2959, 4083, 2169, 3697, 1271, 2412, 3804, 755, 1177, 2149, 1595, 2844,
+    3508, 1325, 3141, 3614, 2140, 1120, 2464, 1715, 4059, 1359, 2020, 2505, 3581, 2821, 3770, 1321,
+    3427, 2786, 1191, 3854, 747, 3314, 1640, 40, 3688, 2765, 1400, 3985, 369, 3279, 493, 2155,
+    1704, 4026, 1275, 2433, 1801, 188, 898, 3625, 3047, 1013, 101, 2901, 830, 303, 3465, 2397,
+    3692, 1683, 848, 3592, 2430, 1551, 147, 1752, 2837, 389, 1610, 3115, 2712, 78, 3896, 695,
+    1856, 4079, 967, 1682, 388, 3829, 674, 160, 2658, 930, 3195, 88, 1712, 681, 2360, 3158,
+    503, 878, 1967, 3135, 1462, 2449, 4007, 1168, 1881, 724, 3448, 2538, 844, 1872, 3880, 1027,
+    2661, 3246, 281, 3615, 2851, 1396, 2671, 2082, 403, 2521, 3295, 1351, 3931, 1563, 3120, 1219,
+    2746, 370, 2245, 1201, 504, 3304, 1074, 3124, 684, 3996, 1035, 2291, 3588, 1384, 3264, 2527,
+    432, 2390, 235, 2993, 2620, 3232, 1547, 3416, 2209, 438, 3822, 2915, 1431, 3345, 1054, 2131,
+    1748, 4070, 2535, 95, 2139, 520, 899, 2576, 3235, 2192, 193, 1710, 2876, 1243, 2330, 3018,
+    619, 1459, 2179, 1010, 470, 3326, 3962, 1627, 1224, 3777, 1828, 492, 2143, 2619, 685, 1950,
+    1028, 3227, 3851, 2843, 2042, 3751, 2692, 2254, 3483, 1988, 2634, 227, 1808, 826, 2066, 1154,
+    2937, 1582, 3433, 2086, 810, 1898, 1038, 2930, 3643, 1849, 1132, 2282, 554, 3970, 149, 2994,
+    342, 1388, 3391, 1056, 3809, 2974, 3531, 1750, 404, 3928, 1033, 3147, 3805, 22, 3547, 378,
+    1806, 3790, 2950, 3498, 1919, 719, 2340, 59, 3458, 746, 2364, 3192, 1101, 3428, 205, 4052,
+    1588, 642, 1870, 1398, 763, 299, 1485, 910, 28, 1430, 3339, 657, 3954, 3065, 279, 3553,
+    3848, 617, 1308, 3709, 6, 4002, 2396, 298, 1406, 721, 2732, 3462, 1992, 2467, 1240, 2666,
+    3695, 2278, 637, 2640, 1697, 1269, 170, 2840, 1354, 2649, 1591, 2378, 678, 2090, 1533, 3216,
+    2457, 819, 85, 1310, 2522, 3133, 1085, 2944, 2023, 2792, 1534, 139, 3834, 1746, 2276, 2972,
+    3606, 2569, 91, 3414, 3016, 2463, 3936, 1913, 3651, 2891, 1149, 2218, 2759, 1557, 2331, 920,
+    1785, 2226, 2794, 1077, 2511, 1478, 613, 3277, 2096, 3956, 1643, 390, 921, 3143, 3541, 1585,
+    792, 1924, 3257, 226, 3656, 2375, 2030, 3325, 738, 3736, 516, 3396, 1296, 2783, 900, 3933,
+    1194, 1987, 2726, 4091, 1656, 245, 3793, 1375, 549, 4037, 1004, 2575, 2910, 854, 1381, 433,
+    2088, 1158, 3989, 2204, 982, 1714, 3215, 581, 2349, 398, 1737, 3568, 439, 1235, 3379, 2564,
+    93, 3310, 422, 3101, 1985, 3438, 2757, 1198, 2543, 105, 2970, 3781, 1499, 196, 1889, 488,
+    3082, 3920, 1207, 2787, 924, 501, 4043, 1023, 1789, 2159, 3025, 214, 1837, 3501, 2286, 165,
+    2914, 3627, 511, 2236, 906, 3402, 1858, 2292, 3275, 290, 3500, 1871, 535, 3668, 2446, 3338,
+    803, 1755, 2730, 522, 3544, 145, 1246, 2703, 1036, 4009, 3004, 892, 2056, 3859, 643, 2922,
+    1333, 4022, 1670, 740, 3877, 246, 1741, 3690, 852, 3411, 2220, 1099, 2812, 2386, 4017, 1060,
+    2476, 311, 1744, 2194, 3193, 1479, 2996, 2478, 68, 3586, 1142, 2565, 4063, 446, 3097, 1766,
+    711, 1507, 3270, 1234, 2887, 411, 2713, 672, 1694, 2489, 1338, 2221, 3104, 1146, 0, 2829,
+    3740, 267, 3196, 1420, 2015, 2925, 3776, 2081, 3316, 1516, 231, 2398, 3171, 167, 1910, 3617,
+    2099, 1014, 2679, 2352, 1387, 978, 2916, 425, 1941, 1347, 605, 1841, 3628, 705, 3245, 2052,
+    2861, 1449, 3791, 660, 3569, 1829, 304, 3753, 1439, 2855, 576, 2068, 856, 1576, 1166, 3555,
+    2164, 2545, 199, 3826, 1968, 3607, 1124, 3937, 2990, 812, 3711, 181, 1641, 4018, 1986, 1509,
+    3006, 2355, 1044, 3640, 2579, 832, 1573, 287, 702, 2529, 1853, 3730, 1397, 2779, 1615, 839,
+    367, 3121, 3700, 118, 3221, 2127, 3812, 2341, 3094, 4075, 2651, 3302, 272, 1723, 1332, 150,
+    3535, 943, 2980, 10, 2451, 1182, 2731, 723, 2265, 3466, 1642, 3803, 3191, 2363, 2767, 309,
+    3999, 877, 3066, 1647, 742, 2336, 1550, 45, 2062, 1213, 2698, 3282, 731, 2647, 3453, 612,
+    2110, 3959, 1707, 658, 347, 4076, 2382, 2983, 3661, 1215, 3397, 656, 992, 4084, 2497, 3350,
+    2272, 1513, 648, 1842, 3518, 525, 1554, 1112, 67, 1613, 452, 2304, 1007, 2758, 3825, 2269,
+    573, 2632, 1866, 3378, 843, 3917, 1980, 3309, 999, 368, 2654, 1227, 84, 3623, 668, 1893,
+    3315, 1342, 2091, 2686, 312, 3318, 2556, 3552, 3125, 336, 3853, 1957, 1061, 2307, 288, 1287,
+    889, 97, 3412, 2849, 1899, 1299, 3377, 979, 1983, 2727, 114, 2956, 2107, 435, 1263, 16,
+    3918, 2631, 1170, 2985, 2515, 901, 2822, 3574, 2443, 3392, 1204, 3876, 3024, 1945, 784, 3181,
+    1658, 4058, 1147, 2167, 1566, 2904, 475, 1352, 4028, 1776, 3051, 767, 2016, 1455, 3020, 1107,
+    2421, 31, 3696, 1086, 3975, 1364, 530, 983, 1758, 2354, 1409, 419, 3014, 1560, 3866, 2748,
+    3151, 1437, 2448, 1134, 3140, 2258, 17, 1728, 539, 3983, 2275, 1676, 3567, 3149, 1886, 2838,
+    896, 1947, 3767, 379, 1651, 3909, 255, 1990, 775, 2854, 2109, 622, 1457, 39, 3590, 1261,
+    2518, 238, 3274, 517, 3726, 164, 2381, 3168, 2085, 202, 2419, 3301, 3964, 2597, 402, 3875,
+    1687, 2856, 566, 3180, 1867, 2941, 2151, 2744, 4034, 621, 3475, 2593, 3650, 687, 3320, 1865,
+    455, 3816, 2021, 237, 3915, 733, 2706, 3744, 3208, 1386, 374, 1055, 2600, 785, 3728, 1604,
+    3480, 264, 3155, 2369, 1094, 2166, 3226, 1324, 3982, 215, 1716, 3710, 3280, 2625, 2146, 472,
+    2965, 931, 2317, 1390, 2616, 3430, 1668, 851, 2785, 3758, 1323, 487, 1661, 976, 2163, 3220,
+    734, 3562, 1482, 2315, 158, 857, 3645, 276, 1538, 3230, 958, 1810, 2162, 52, 1165, 2370,
+    761, 2652, 3505, 959, 1666, 3450, 1451, 2156, 801, 2471, 3033, 3849, 1484, 190, 2372, 552,
+    1214, 2133, 1467, 757, 3491, 2745, 645, 1807, 2562, 3122, 873, 2410, 395, 1059, 3979, 1496,
+    3464, 1925, 3873, 3058, 716, 2025, 1200, 3583, 590, 1053, 3460, 2287, 2909, 3723, 113, 1335,
+    2716, 1963, 962, 2608, 3768, 1738, 3327, 1216, 2029, 2860, 182, 1276, 3957, 2890, 1667, 3610,
+    1329, 1769, 394, 2945, 2514, 519, 3038, 207, 1139, 3540, 1894, 628, 3354, 2035, 3056, 3974,
+    2533, 3329, 2868, 4065, 42, 1425, 3795, 314, 1063, 3629, 1380, 2936, 2038, 1648, 2773, 815,
+    2400, 146, 1684, 1108, 305, 4013, 2981, 66, 2450, 1997, 1571, 176, 833, 1897, 3499, 2394,
+    473, 3837, 328, 3260, 1152, 615, 2285, 2687, 764, 3774, 2380, 3129, 745, 2551, 333, 3046,
+    2093, 3322, 1167, 2234, 3630, 1248, 1836, 3973, 2664, 1633, 62, 2227, 2825, 1266, 863, 1727,
+    119, 995, 502, 1742, 2598, 2060, 2951, 3376, 2298, 1907, 99, 3944, 728, 3597, 324, 3103,
+    3722, 595, 2801, 3653, 2161, 1512, 2563, 1834, 3867, 3178, 2705, 4035, 3276, 1448, 2800, 1069,
+    1699, 3366, 2128, 2908, 1597, 4012, 3057, 3, 3492, 1729, 529, 1504, 3424, 1888, 1052, 3911,
+    2775, 65, 4040, 730, 1979, 275, 3347, 2327, 543, 2955, 3901, 1020, 3657, 289, 3461, 2669,
+    3718, 1939, 2347, 3425, 1218, 459, 928, 1583, 691, 2791, 3426, 1113, 2488, 3306, 1883, 1189,
+    2124, 1423, 3261, 2427, 770, 3343, 494, 984, 1365, 322, 707, 1205, 2477, 308, 646, 3976,
+    2984, 802, 1306, 103, 2496, 445, 1966, 1445, 1022, 2588, 4060, 2102, 243, 3716, 2348, 563,
+    903, 2389, 3076, 1590, 3788, 2816, 954, 1477, 3507, 879, 1399, 2605, 1788, 2338, 1523, 587,
+    3037, 1302, 3881, 795, 3085, 3707, 2493, 4010, 3179, 359, 1520, 2064, 544, 1441, 70, 2637,
+    4092, 964, 1813, 87, 1301, 2701, 3605, 3100, 2261, 3698, 1784, 2142, 3042, 3678, 1929, 2231,
+    201, 2441, 3912, 1891, 3545, 925, 3721, 2242, 3159, 357, 1196, 2907, 834, 1405, 3116, 1626,
+    1879, 3577, 1345, 212, 2581, 578, 3183, 2013, 291, 2182, 3128, 415, 732, 4032, 3209, 1047,
+    2138, 371, 2638, 230, 1508, 2123, 89, 1803, 1247, 2243, 3839, 2722, 3142, 3906, 2230, 736,
+    3036, 405, 3514, 2884, 3872, 1946, 218, 1635, 818, 2828, 3388, 465, 974, 1584, 3233, 1225,
+    3408, 1546, 2832, 604, 3043, 1254, 2707, 625, 3844, 1892, 3534, 2324, 3352, 2693, 141, 3833,
+    375, 2919, 1042, 2129, 3477, 1778, 1217, 4080, 2519, 3725, 1650, 3489, 2867, 1958, 90, 2483,
+    3587, 1619, 3292, 1896, 2841, 3537, 1025, 2770, 602, 3511, 869, 174, 1732, 957, 2811, 3566,
+    1624, 2445, 2053, 528, 882, 2328, 1143, 4029, 2461, 5, 1452, 3819, 2362, 123, 2750, 864,
+    3802, 451, 1083, 2253, 1700, 3449, 129, 1620, 2831, 918, 194, 1674, 545, 2028, 1093, 3385,
+    2229, 636, 2653, 3958, 875, 2379, 18, 2935, 686, 1136, 161, 2136, 1312, 939, 3787, 1389,
+    2932, 606, 947, 3994, 1260, 676, 2359, 3755, 3053, 1890, 2507, 1334, 3743, 461, 1935, 1222,
+    191, 3737, 1353, 3134, 1698, 3478, 2995, 600, 1833, 3242, 1095, 2629, 1850, 4087, 596, 2132,
+    1722, 2536, 3207, 3733, 326, 2523, 2073, 3308, 1358, 2473, 3072, 3750, 1348, 3991, 2452, 1511,
+    3040, 3715, 1660, 407, 1426, 3250, 3632, 1495, 1863, 2760, 3176, 3949, 505, 2395, 3132, 302,
+    1933, 3713, 2168, 2465, 184, 3174, 1680, 392, 1438, 252, 3288, 2148, 2885, 3403, 2350, 3107,
+    662, 2708, 993, 3938, 2559, 356, 1463, 2665, 3797, 2189, 531, 3519, 829, 2900, 1367, 3598,
+    2964, 26, 1974, 814, 1491, 4094, 1103, 468, 3902, 688, 2002, 1012, 2728, 274, 3187, 846,
+    49, 1130, 2005, 3084, 2742, 725, 2100, 484, 3441, 2323, 808, 1530, 2662, 3472, 1681, 771,
+    2762, 1210, 364, 3027, 1537, 3471, 2057, 4095, 2642, 996, 3676, 647, 1145, 19, 1489, 4015,
+    1860, 3256, 2190, 72, 779, 2059, 3655, 941, 294, 1285, 2979, 1608, 254, 3371, 2472, 350,
+    1040, 3895, 1337, 3516, 2824, 641, 3067, 2303, 1711, 3571, 75, 3398, 2206, 664, 3582, 1902,
+    4069, 2495, 3419, 261, 3852, 1183, 2568, 3935, 1068, 284, 3769, 2010, 50, 1109, 2178, 4081,
+    3336, 2534, 1726, 3862, 886, 2709, 1090, 584, 2224, 2958, 1556, 1859, 2650, 3807, 786, 2573,
+    1119, 454, 1614, 3406, 2889, 1192, 3157, 1759, 3389, 2509, 1918, 3765, 2267, 1172, 1996, 1562,
+    3317, 2320, 2695, 224, 2180, 1800, 3637, 295, 2897, 1244, 2553, 1518, 2982, 1756, 1309, 2788,
+    478, 1376, 861, 2249, 1575, 1931, 109, 2976, 1636, 3244, 1282, 2872, 3660, 3034, 560, 1473,
+    134, 975, 3523, 570, 2334, 29, 3641, 1369, 3340, 122, 3932, 410, 3144, 2213, 1753, 315,
+    3638, 2835, 3840, 1370, 2391, 4041, 153, 2290, 726, 3978, 110, 927, 3087, 649, 3969, 2803,
+    794, 506, 1763, 1000, 3348, 1284, 2601, 945, 2044, 3267, 768, 3922, 354, 3778, 990, 2284,
+    3102, 1812, 2942, 3554, 559, 3182, 3684, 796, 2118, 2486, 629, 1780, 890, 2492, 3808, 1878,
+    2357, 3007, 2063, 1424, 3253, 1936, 2926, 1782, 2513, 868, 1977, 3386, 1331, 980, 3003, 3361,
+    2288, 1949, 858, 343, 1869, 640, 2769, 1480, 3063, 1186, 2818, 1501, 3563, 2567, 74, 3163,
+    1911, 3488, 4016, 2968, 571, 3856, 44, 1549, 4006, 414, 2373, 1148, 1932, 2636, 211, 3476,
+    671, 3865, 135, 2626, 1081, 2356, 1392, 2752, 313, 4053, 3434, 221, 2098, 1350, 323, 3281,
+    735, 3892, 277, 2734, 1106, 3929, 737, 306, 3799, 1164, 2814, 2365, 598, 3612, 143, 1469,
+    618, 1226, 2690, 3202, 3549, 1080, 3775, 2041, 490, 3503, 2388, 2072, 464, 1826, 1418, 953,
+    2280, 317, 1250, 2447, 1654, 3123, 2339, 3473, 2699, 1819, 3098, 3585, 593, 3290, 2103, 1505,
+];
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::shared::SampleRng;
+
+    /// Ranks made with Ulichney's void and cluster method, from a fixed seed
+    fn generate() -> Vec<u16> {
+        let size = BLUE_NOISE_SIZE;
+        let n = size * size;
+        let sigma = 1.5f32;
+
+        // Gaussian weight for each toroidal offset
+        let mut kernel = vec![0.0f32; n];
+        for dy in 0..size {
+            for dx in 0..size {
+                let wrap = |d: usize| d.min(size - d) as f32;
+                let distance_sq = wrap(dx).powi(2) + wrap(dy).powi(2);
+                kernel[dy * size + dx] = (-distance_sq / (2.0 * sigma * sigma)).exp();
+            }
+        }
+
+        // Energy of each pixel is the sum of the kernel over the set pixels around it
+        let toggle = |pattern: &mut Vec<bool>, energy: &mut Vec<f32>, p: usize| {
+            pattern[p] = !pattern[p];
+            let sign = if pattern[p] { 1.0 } else { -1.0 };
+            let (px, py) = (p % size, p / size);
+            for qy in 0..size {
+                for qx in 0..size {
+                    let dx = (qx + size - px) % size;
+                    let dy = (qy + size - py) % size;
+                    energy[qy * size + qx] += sign * kernel[dy * size + dx];
+                }
+            }
+        };
+        // The tightest cluster is the set pixel with the highest energy, the largest void the unset
+        // pixel with the lowest
+        let tightest_cluster = |pattern: &Vec<bool>, energy: &Vec<f32>| {
+            (0..n).filter(|&p| pattern[p]).max_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap()).unwrap()
+        };
+        let largest_void = |pattern: &Vec<bool>, energy: &Vec<f32>| {
+            (0..n).filter(|&p| !pattern[p]).min_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap()).unwrap()
+        };
+
+        // Initial pattern of a tenth of the pixels set at random, then relaxed by moving points from
+        // clusters into voids until that no longer changes anything
+        let mut rng = SampleRng::seed_from_u64(0);
+        let mut pattern = vec![false; n];
+        let mut energy = vec![0.0f32; n];
+        let initial = n / 10;
+        let mut set = 0;
+        while set < initial {
+            let p = rng.gen_range(0..n);
+            if !pattern[p] {
+                toggle(&mut pattern, &mut energy, p);
+                set += 1;
+            }
+        }
+        for _ in 0..n {
+            let cluster = tightest_cluster(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, cluster);
+            let void = largest_void(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, void);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut rank = vec![0u16; n];
+        // Rank the initial points by removing clusters first
+        let (mut removed, mut removed_energy) = (pattern.clone(), energy.clone());
+        for r in (0..initial).rev() {
+            let cluster = tightest_cluster(&removed, &removed_energy);
+            toggle(&mut removed, &mut removed_energy, cluster);
+            rank[cluster] = r as u16;
+        }
+        // Then the rest by filling voids
+        for r in initial..n {
+            let void = largest_void(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, void);
+            rank[void] = r as u16;
+        }
+
+        rank
+    }
+
+    #[test]
+    fn ranks_match_generator() {
+        assert!(generate().iter().eq(BLUE_NOISE_RANKS.iter()));
+    }
+}
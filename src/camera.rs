@@ -1,6 +1,112 @@
 use serde::{Serialize, Deserialize};
 
-use crate::shared::{Point3, Ray, Vec3, degrees_to_radians, random_in_unit_disk};
+use crate::shared::{Point3, Ray, Vec2, Vec3, degrees_to_radians, sample_unit_disk};
+
+/// Placement and lens of a camera, independent of the image it renders to
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    #[serde(default = "default_vup")]
+    pub vup: Vec3,
+    #[serde(default)]
+    pub projection: ProjectionDesc,
+    // Vertical field of view in degrees, for perspective cameras
+    #[serde(default = "default_vfov")]
+    pub vfov: f32,
+    #[serde(default)]
+    pub aperture: f32,
+    // Defaults to the distance between lookfrom and lookat
+    #[serde(default)]
+    pub focus_dist: Option<f32>,
+    // Times the shutter opens and closes, where moving objects are blurred in between
+    #[serde(default)]
+    pub shutter_open: f32,
+    #[serde(default)]
+    pub shutter_close: f32,
+}
+
+fn default_vup() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+fn default_vfov() -> f32 {
+    90.0
+}
+
+/// How directions from the camera map onto the image. Only the perspective projection has a lens,
+/// so the others are always in focus.
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Projection {
+    // Thin lens perspective, using vfov, aperture and focus_dist
+    Perspective,
+    // Parallel rays, for elevations and plans, covering height world units vertically
+    Orthographic { height: f32 },
+    // Equidistant fisheye, where the angle from the view direction grows linearly with the
+    // distance from the image center up to fov/2 degrees at the edge of the circle fitting in
+    // the image. Outside that circle is black.
+    Fisheye { fov: f32 },
+    // Full 360 by 180 degree latitude/longitude panorama, with the view direction in the center,
+    // for a 2:1 image
+    Equirectangular,
+}
+
+/// Projection as written in scene files, e.g. `{ "type": "fisheye", "fov": 180 }`. Internally
+/// tagged enums can't be read back by bincode, so cameras sent to workers hold a Projection.
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProjectionDesc {
+    Perspective,
+    Orthographic { height: f32 },
+    Fisheye { fov: f32 },
+    Equirectangular,
+}
+
+impl Default for ProjectionDesc {
+    fn default() -> Self {
+        ProjectionDesc::Perspective
+    }
+}
+
+impl From<ProjectionDesc> for Projection {
+    fn from(desc: ProjectionDesc) -> Self {
+        match desc {
+            ProjectionDesc::Perspective => Projection::Perspective,
+            ProjectionDesc::Orthographic { height } => Projection::Orthographic { height },
+            ProjectionDesc::Fisheye { fov } => Projection::Fisheye { fov },
+            ProjectionDesc::Equirectangular => Projection::Equirectangular,
+        }
+    }
+}
+
+impl CameraSettings {
+    /// Create a camera for an image of width,height
+    pub fn build(&self, width: usize, height: usize) -> Camera {
+        let aspect_ratio = (width as f32) / (height as f32);
+        let focus_dist = self.focus_dist.unwrap_or_else(|| (self.lookfrom - self.lookat).length());
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            focus_dist,
+            self.projection.into(),
+        ).with_shutter(self.shutter_open, self.shutter_close)
+    }
+
+    /// Create a camera which moves from these settings when the shutter opens to end when it closes
+    pub fn build_moving(&self, end: &CameraSettings, width: usize, height: usize) -> Camera {
+        let mut camera = self.build(width, height);
+        camera.end = Some(Box::new(end.build(width, height)));
+        camera
+    }
+}
 
 /// Representation of a camera in the scene
 #[derive(Clone)]
@@ -12,7 +118,14 @@ pub struct Camera {
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
+    w: Vec3,
     lens_radius: f32,
+    aspect_ratio: f32,
+    projection: Projection,
+    shutter_open: f32,
+    shutter_close: f32,
+    // Camera at the time the shutter closes, if it moves while open
+    end: Option<Box<Camera>>,
 }
 
 impl Camera {
@@ -24,6 +137,7 @@ impl Camera {
         aspect_ratio: f32,
         aperture: f32,
         focus_dist: f32,
+        projection: Projection,
     ) -> Self {
         let theta = degrees_to_radians(vfov);
         let h = f32::tan(theta / 2.0);
@@ -35,9 +149,19 @@ impl Camera {
         let v = Vec3::cross(w, u);
 
         let origin = lookfrom;
-        let horizontal = focus_dist * viewport_width * u;
-        let vertical = focus_dist * viewport_height * v;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
+        let (horizontal, vertical, lower_left_corner, lens_radius) = match projection {
+            // The image is a window through the view plane at the camera
+            Projection::Orthographic { height } => {
+                let horizontal = aspect_ratio * height * u;
+                let vertical = height * v;
+                (horizontal, vertical, origin - horizontal / 2.0 - vertical / 2.0, 0.0)
+            },
+            _ => {
+                let horizontal = focus_dist * viewport_width * u;
+                let vertical = focus_dist * viewport_height * v;
+                (horizontal, vertical, origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w, aperture / 2.0)
+            },
+        };
 
         Camera {
             origin: origin,
@@ -46,18 +170,178 @@ impl Camera {
             lower_left_corner: lower_left_corner,
             u: u,
             v: v,
-            lens_radius: aperture / 2.0,
+            w: w,
+            lens_radius: lens_radius,
+            aspect_ratio: aspect_ratio,
+            projection: projection,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            end: None,
         }
     }
 
-    /// Generate a ray using the lens model
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
-        let offset = self.u * rd.x + self.v * rd.y;
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Self {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    /// Whether the shutter is open for a while or the camera moves, so rays need times picking
+    pub fn has_exposure(&self) -> bool {
+        self.shutter_close > self.shutter_open || self.end.is_some()
+    }
+
+    /// Generate a ray through (s, t) in [0, 1]^2 from the bottom left of the image, with lens in
+    /// [0, 1)^2 picking the point on the lens and shutter in [0, 1) the time while the shutter is
+    /// open. None where the projection doesn't cover the image, such as outside a fisheye's circle.
+    pub fn get_ray(&self, s: f32, t: f32, lens: Vec2, shutter: f32) -> Option<Ray> {
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => {
+                let rd = self.lens_radius * sample_unit_disk(lens);
+                let offset = self.u * rd.x + self.v * rd.y;
+                let origin = self.origin + offset;
+                (origin, self.lower_left_corner + s * self.horizontal + t * self.vertical - origin)
+            },
+            Projection::Orthographic { .. } => {
+                (self.lower_left_corner + s * self.horizontal + t * self.vertical, -self.w)
+            },
+            Projection::Fisheye { fov } => {
+                // Offset from the center, scaled so the circle fitting the image has radius 1
+                let offset = 2.0 * Vec2::new(s - 0.5, t - 0.5) * Vec2::new(self.aspect_ratio, 1.0);
+                let r = offset.length() / self.aspect_ratio.min(1.0);
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * degrees_to_radians(fov) / 2.0;
+                let phi = offset.y.atan2(offset.x);
+                let direction = theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+                (self.origin, direction)
+            },
+            Projection::Equirectangular => {
+                let longitude = 2.0 * std::f32::consts::PI * (s - 0.5);
+                let latitude = std::f32::consts::PI * (t - 0.5);
+                let horizontal = longitude.sin() * self.u - longitude.cos() * self.w;
+                (self.origin, latitude.cos() * horizontal + latitude.sin() * self.v)
+            },
+        };
+
+        let time = self.shutter_open + shutter * (self.shutter_close - self.shutter_open);
+        let mut ray = Ray::new(origin, direction, time);
+        // A moving camera is interpolated between the rays it makes at either end
+        if let Some(end_ray) = self.end.as_ref().and_then(|end| end.get_ray(s, t, lens, shutter)) {
+            ray.origin = ray.origin.lerp(end_ray.origin, shutter);
+            ray.direction = ray.direction.lerp(end_ray.direction, shutter);
+        }
+        Some(ray)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(projection: ProjectionDesc) -> CameraSettings {
+        CameraSettings {
+            lookfrom: Point3::new(0.0, 0.0, 5.0),
+            lookat: Point3::ZERO,
+            vup: default_vup(),
+            projection: projection,
+            vfov: default_vfov(),
+            aperture: 0.1,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+        }
+    }
+
+    const PROJECTIONS: [ProjectionDesc; 4] = [
+        ProjectionDesc::Perspective,
+        ProjectionDesc::Orthographic { height: 2.0 },
+        ProjectionDesc::Fisheye { fov: 180.0 },
+        ProjectionDesc::Equirectangular,
+    ];
+
+    #[test]
+    fn scene_file_projection() {
+        let json = r#"{ "lookfrom": [0, 0, 5], "lookat": [0, 0, 0], "projection": { "type": "fisheye", "fov": 180 } }"#;
+        let settings: CameraSettings = serde_json::from_str(json).unwrap();
+        assert!(settings.projection == ProjectionDesc::Fisheye { fov: 180.0 });
+        let json = r#"{ "lookfrom": [0, 0, 5], "lookat": [0, 0, 0] }"#;
+        let settings: CameraSettings = serde_json::from_str(json).unwrap();
+        assert!(settings.projection == ProjectionDesc::Perspective);
+    }
+
+    /// Cameras are sent to workers with bincode, so must come back making the same rays
+    #[test]
+    fn bincode_round_trip() {
+        for &projection in PROJECTIONS.iter() {
+            let still = settings(projection).build(200, 100);
+            let mut end = settings(projection);
+            end.lookat = Point3::new(1.0, 0.0, 0.0);
+            let moving = settings(projection).build_moving(&end, 200, 100);
+            for camera in [still, moving] {
+                let read: Camera = bincode::deserialize(&bincode::serialize(&camera).unwrap()).unwrap();
+                for &(s, t) in [(0.5, 0.5), (0.1, 0.9), (0.9, 0.2)].iter() {
+                    let lens = Vec2::new(0.3, 0.7);
+                    let ray = |camera: &Camera| camera.get_ray(s, t, lens, 0.25).map(|r| (r.origin, r.direction, r.time));
+                    assert_eq!(ray(&camera), ray(&read));
+                }
+            }
+        }
+    }
+
+    /// Direction of the ray through s, t from the middle of the lens, normalized
+    fn direction(projection: ProjectionDesc, s: f32, t: f32) -> Option<Vec3> {
+        let camera = settings(projection).build(200, 100);
+        camera.get_ray(s, t, Vec2::splat(0.5), 0.0).map(|ray| ray.direction.normalize())
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn projections_look_at_the_center() {
+        for &projection in PROJECTIONS.iter() {
+            assert_near(direction(projection, 0.5, 0.5).unwrap(), -Vec3::Z);
+        }
+    }
+
+    #[test]
+    fn perspective() {
+        let corner = direction(ProjectionDesc::Perspective, 0.0, 0.0).unwrap();
+        assert!(corner.x < 0.0 && corner.y < 0.0 && corner.z < 0.0);
+        // The vertical field of view spans the image height
+        let top = direction(ProjectionDesc::Perspective, 0.5, 1.0).unwrap();
+        assert!((top.angle_between(-Vec3::Z) - degrees_to_radians(default_vfov()) / 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn orthographic() {
+        let camera = settings(ProjectionDesc::Orthographic { height: 2.0 }).build(200, 100);
+        let ray = camera.get_ray(0.0, 0.0, Vec2::new(0.9, 0.1), 0.0).unwrap();
+        assert_near(ray.direction.normalize(), -Vec3::Z);
+        assert!((ray.origin.x + 2.0).abs() < 1e-4 && (ray.origin.y + 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fisheye() {
+        let fisheye = ProjectionDesc::Fisheye { fov: 180.0 };
+        // The circle fits the height of the wide image, so its edges reach sideways and up
+        assert_near(direction(fisheye, 0.5, 1.0).unwrap(), Vec3::Y);
+        assert_near(direction(fisheye, 0.25, 0.5).unwrap(), -Vec3::X);
+        assert!(direction(fisheye, 0.2, 0.5).is_none());
+        assert!(direction(fisheye, 0.0, 0.0).is_none());
+        assert!(direction(fisheye, 1.0, 1.0).is_none());
+    }
 
-        return Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-        );
+    #[test]
+    fn equirectangular() {
+        let equirectangular = ProjectionDesc::Equirectangular;
+        assert_near(direction(equirectangular, 0.75, 0.5).unwrap(), Vec3::X);
+        assert_near(direction(equirectangular, 0.0, 0.5).unwrap(), Vec3::Z);
+        // The bottom and top rows look straight down and up
+        assert_near(direction(equirectangular, 0.0, 0.0).unwrap(), -Vec3::Y);
+        assert_near(direction(equirectangular, 1.0, 1.0).unwrap(), Vec3::Y);
     }
 }
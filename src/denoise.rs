@@ -0,0 +1,130 @@
+use serde::{Serialize, Deserialize};
+
+use crate::aov::Aov;
+use crate::hdr::HdrImage;
+use crate::render::RenderOutput;
+use crate::shared::Color;
+
+// Albedo below this is treated as black when separating lighting from albedo
+const ALBEDO_EPSILON: f32 = 1e-3;
+
+/// Joint bilateral filter which smooths noise in the beauty pass while keeping edges found in the
+/// albedo and normal AOVs. Lighting is filtered separately from albedo, so texture detail which is
+/// present in the albedo isn't blurred.
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Denoiser {
+    // Half width of the filter window in pixels
+    pub radius: u32,
+    pub sigma_spatial: f32,
+    // Applies to lighting compressed into [0, 1)
+    pub sigma_color: f32,
+    pub sigma_normal: f32,
+    pub sigma_albedo: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            radius: 6,
+            sigma_spatial: 3.0,
+            sigma_color: 0.25,
+            sigma_normal: 0.2,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+fn to_color(p: &image::Rgb<f32>) -> Color {
+    Color::new(p[0], p[1], p[2])
+}
+
+/// Albedo to divide out of the beauty pass, leaving black channels alone
+fn demodulation(albedo: Color) -> Color {
+    let channel = |a: f32| if a > ALBEDO_EPSILON { a } else { 1.0 };
+    Color::new(channel(albedo.x), channel(albedo.y), channel(albedo.z))
+}
+
+/// Gaussian weight for a squared distance
+fn gaussian(distance_sq: f32, sigma: f32) -> f32 {
+    (-distance_sq / (2.0 * sigma * sigma)).exp()
+}
+
+impl Denoiser {
+    /// Denoise the beauty pass, guided by whichever of the albedo and normal AOVs are present
+    pub fn apply(&self, output: &RenderOutput) -> HdrImage {
+        let (width, height) = output.beauty.dimensions();
+        let pixel_count = (width * height) as usize;
+        let index = |x: u32, y: u32| (y * width + x) as usize;
+
+        let buffer = |aov: Aov| -> Option<Vec<Color>> {
+            output.aov(aov).map(|img| img.pixels().map(to_color).collect())
+        };
+        let albedo = buffer(Aov::Albedo);
+        let normal = buffer(Aov::Normal);
+
+        let demodulate: Vec<Color> = match &albedo {
+            Some(albedo) => albedo.iter().map(|&a| demodulation(a)).collect(),
+            None => vec![Color::ONE; pixel_count],
+        };
+        let lighting: Vec<Color> = output.beauty.pixels().zip(&demodulate)
+            .map(|(p, &d)| {
+                let c = to_color(p) / d;
+                // Drop NaNs and negative values rather than spreading them
+                if c.is_finite() { c.max(Color::ZERO) } else { Color::ZERO }
+            })
+            .collect();
+
+        // The colour edge stopping term compares each neighbour with a prefiltered guide for the
+        // centre, so noise alone isn't taken for an edge while outliers such as fireflies are
+        // rejected. Lighting is compressed so that very bright values don't dominate.
+        let compressed: Vec<Color> = lighting.iter().map(|&c| c / (Color::ONE + c)).collect();
+        let mut guide = vec![Color::ZERO; pixel_count];
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Color::ZERO;
+                let mut count = 0.0;
+                for qy in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                    for qx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                        sum += compressed[index(qx, qy)];
+                        count += 1.0;
+                    }
+                }
+                guide[index(x, y)] = sum / count;
+            }
+        }
+
+        let radius = self.radius as i64;
+        let mut img = HdrImage::new(width, height);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let p = index(x, y);
+            let mut sum = Color::ZERO;
+            let mut weight_sum = 0.0;
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let qx = x as i64 + dx;
+                    let qy = y as i64 + dy;
+                    if qx < 0 || qy < 0 || qx >= width as i64 || qy >= height as i64 {
+                        continue;
+                    }
+                    let q = index(qx as u32, qy as u32);
+
+                    let mut weight = gaussian((dx * dx + dy * dy) as f32, self.sigma_spatial)
+                        * gaussian((guide[p] - compressed[q]).length_squared(), self.sigma_color);
+                    if let Some(normal) = &normal {
+                        weight *= gaussian((normal[p] - normal[q]).length_squared(), self.sigma_normal);
+                    }
+                    if let Some(albedo) = &albedo {
+                        weight *= gaussian((albedo[p] - albedo[q]).length_squared(), self.sigma_albedo);
+                    }
+                    sum += weight * lighting[q];
+                    weight_sum += weight;
+                }
+            }
+            let filtered = if weight_sum > 0.0 { sum / weight_sum } else { lighting[p] };
+            *pixel = image::Rgb((filtered * demodulate[p]).into());
+        }
+        img
+    }
+}
@@ -0,0 +1,170 @@
+use serde::{Serialize, Deserialize};
+
+/// Shape of the reconstruction filter which weights each sample's contribution to nearby pixels
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Kernel {
+    // Average of the samples within the radius, with the default radius of half a pixel each pixel
+    // is just the average of its own samples
+    #[serde(rename = "box")]
+    Box,
+    #[serde(rename = "tent")]
+    Tent,
+    // Gaussian with a standard deviation of a third of the radius, shifted to reach zero there
+    #[serde(rename = "gaussian")]
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3, stretched over the radius
+    #[serde(rename = "mitchell")]
+    Mitchell,
+    // Sinc windowed by a sinc the width of the radius
+    #[serde(rename = "lanczos")]
+    Lanczos,
+}
+
+impl Kernel {
+    /// Radius used when none is given, in pixels
+    pub fn default_radius(self) -> f32 {
+        match self {
+            Kernel::Box => 0.5,
+            Kernel::Tent => 1.0,
+            Kernel::Gaussian => 1.5,
+            Kernel::Mitchell => 2.0,
+            Kernel::Lanczos => 3.0,
+        }
+    }
+}
+
+/// Reconstruction filter, which is separable into the same kernel horizontally and vertically
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    pub kernel: Kernel,
+    // In pixels, defaults to a radius suiting the kernel
+    pub radius: Option<f32>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            kernel: Kernel::Box,
+            radius: None,
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    let px = std::f32::consts::PI * x;
+    px.sin() / px
+}
+
+impl Filter {
+    pub fn radius(&self) -> f32 {
+        self.radius.unwrap_or_else(|| self.kernel.default_radius()).max(0.0)
+    }
+
+    /// Number of pixels around a pixel which its samples can reach
+    pub fn pixel_reach(&self) -> u32 {
+        (self.radius() - 0.5).ceil().max(0.0) as u32
+    }
+
+    /// Weight of a sample at offset d pixels from a pixel centre, along one axis
+    pub fn evaluate(&self, d: f32) -> f32 {
+        let radius = self.radius();
+        // The box includes its lower edge but not its upper, so a sample on the border between
+        // two pixels only counts towards one of them
+        if d < -radius || d >= radius {
+            return 0.0;
+        }
+        let x = d.abs();
+        match self.kernel {
+            Kernel::Box => 1.0,
+            Kernel::Tent => radius - x,
+            Kernel::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            },
+            Kernel::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                // The kernel is defined over [-2, 2]
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                }
+            },
+            Kernel::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+
+    /// Weight of a sample at offset (dx, dy) pixels from a pixel centre
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate(dx) * self.evaluate(dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNELS: [Kernel; 5] = [Kernel::Box, Kernel::Tent, Kernel::Gaussian, Kernel::Mitchell, Kernel::Lanczos];
+
+    fn filter(kernel: Kernel) -> Filter {
+        Filter { kernel, radius: None }
+    }
+
+    #[test]
+    fn zero_outside_radius() {
+        for &kernel in KERNELS.iter() {
+            let filter = filter(kernel);
+            let radius = filter.radius();
+            assert!(filter.evaluate(0.0) > 0.0);
+            for &d in [radius, -radius - 0.01, radius + 0.01, 100.0].iter() {
+                assert_eq!(filter.evaluate(d), 0.0, "{} at {}", radius, d);
+            }
+            assert_eq!(filter.weight(0.0, radius), 0.0);
+        }
+        assert_eq!(Filter { kernel: Kernel::Tent, radius: Some(-1.0) }.evaluate(0.0), 0.0);
+    }
+
+    #[test]
+    fn kernel_values() {
+        assert_eq!(filter(Kernel::Box).evaluate(-0.5), 1.0);
+        assert_eq!(filter(Kernel::Box).evaluate(0.5), 0.0);
+        assert_eq!(filter(Kernel::Tent).evaluate(0.25), 0.75);
+        assert!((filter(Kernel::Mitchell).evaluate(0.0) - 8.0 / 9.0).abs() < 1e-6);
+        // Mitchell and Lanczos have negative lobes, and Lanczos crosses zero at whole pixels
+        assert!(filter(Kernel::Mitchell).evaluate(1.5) < 0.0);
+        assert!(filter(Kernel::Lanczos).evaluate(1.5) < 0.0);
+        assert!(filter(Kernel::Lanczos).evaluate(1.0).abs() < 1e-6);
+        assert!(filter(Kernel::Lanczos).evaluate(2.0).abs() < 1e-6);
+        let gaussian = filter(Kernel::Gaussian);
+        assert!(gaussian.evaluate(0.5) < gaussian.evaluate(0.0) && gaussian.evaluate(1.4) > 0.0);
+        assert_eq!(filter(Kernel::Tent).weight(0.5, 0.5), 0.25);
+    }
+
+    #[test]
+    fn default_kernels_sum_to_one() {
+        // With their default radii these kernels weight a constant image back to the same constant
+        for &kernel in [Kernel::Box, Kernel::Tent, Kernel::Mitchell].iter() {
+            let filter = filter(kernel);
+            for i in 0..10 {
+                let t = i as f32 / 10.0;
+                let sum: f32 = (-4..=4).map(|n| filter.evaluate(t + n as f32)).sum();
+                assert!((sum - 1.0).abs() < 1e-5, "sum {} at {}", sum, t);
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_reach() {
+        let reaches: Vec<u32> = KERNELS.iter().map(|&kernel| filter(kernel).pixel_reach()).collect();
+        assert_eq!(reaches, [0, 1, 1, 2, 3]);
+        assert_eq!(Filter { kernel: Kernel::Box, radius: Some(0.6) }.pixel_reach(), 1);
+    }
+}
@@ -0,0 +1,346 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+#[cfg(feature = "gui")]
+use std::io::BufWriter;
+use std::path::Path;
+
+#[cfg(feature = "gui")]
+use crate::tonemap::ToneMap;
+
+/// A linear floating point RGB image
+pub type HdrImage = image::ImageBuffer<image::Rgb<f32>, Vec<f32>>;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Save an image, picking the format from the file extension. Radiance .hdr and .pfm files keep
+/// the full range, other formats are tone mapped to 8-bit.
+#[cfg(feature = "gui")]
+pub fn save(path: &Path, img: &HdrImage, tonemap: &ToneMap) -> io::Result<()> {
+    save_with(path, img, |img| tonemap.apply(img))
+}
+
+/// Save an image like `save`, with to_ldr converting it for 8-bit formats
+#[cfg(feature = "gui")]
+pub fn save_with(path: &Path, img: &HdrImage, to_ldr: impl FnOnce(&HdrImage) -> image::RgbImage) -> io::Result<()> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("hdr") => write_radiance(&mut BufWriter::new(std::fs::File::create(path)?), img),
+        Some("pfm") => write_pfm(&mut BufWriter::new(std::fs::File::create(path)?), img),
+        _ => to_ldr(img).save(path).map_err(|e| io::Error::new(io::ErrorKind::Other, e)),
+    }
+}
+
+/// Load a Radiance .hdr or .pfm image, picking the format from the file extension
+pub fn load(path: &Path) -> io::Result<HdrImage> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    let image = match extension.as_deref() {
+        Some("hdr") => read_radiance(&mut reader)?,
+        Some("pfm") => read_pfm(&mut reader)?,
+        _ => return Err(invalid_data(format!("unknown HDR image format for {}", path.display()))),
+    };
+    // Environment maps and textures need at least one texel to look up
+    if image.width() == 0 || image.height() == 0 {
+        return Err(invalid_data(format!("{} has no pixels", path.display())));
+    }
+    Ok(image)
+}
+
+/// Read a Portable Float Map, in either its colour (PF) or greyscale (Pf) form
+pub fn read_pfm(reader: &mut impl BufRead) -> io::Result<HdrImage> {
+    // The header is three whitespace separated tokens after the magic, followed by a single
+    // whitespace character
+    let mut tokens: Vec<String> = Vec::new();
+    let mut token = String::new();
+    while tokens.len() < 4 {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        if byte[0].is_ascii_whitespace() {
+            if !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+        } else {
+            token.push(byte[0] as char);
+        }
+    }
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        magic => return Err(invalid_data(format!("not a PFM file (magic '{}')", magic))),
+    };
+    let width: u32 = tokens[1].parse().map_err(|_| invalid_data("invalid PFM width"))?;
+    let height: u32 = tokens[2].parse().map_err(|_| invalid_data("invalid PFM height"))?;
+    let scale: f32 = tokens[3].parse().map_err(|_| invalid_data("invalid PFM scale"))?;
+    let little_endian = scale < 0.0;
+
+    let mut data = vec![0u8; width as usize * height as usize * channels * 4];
+    reader.read_exact(&mut data)?;
+    let values: Vec<f32> = data.chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+    }).collect();
+
+    // Rows are stored bottom to top
+    let mut img = HdrImage::new(width, height);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let i = ((height - y - 1) * width + x) as usize * channels;
+        *pixel = if channels == 3 {
+            image::Rgb([values[i], values[i + 1], values[i + 2]])
+        } else {
+            image::Rgb([values[i]; 3])
+        };
+    }
+    Ok(img)
+}
+
+fn rgbe_to_rgb(rgbe: [u8; 4]) -> image::Rgb<f32> {
+    if rgbe[3] == 0 {
+        return image::Rgb([0.0; 3]);
+    }
+    let f = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+    image::Rgb([rgbe[0] as f32 * f, rgbe[1] as f32 * f, rgbe[2] as f32 * f])
+}
+
+/// Read one scanline of RGBE pixels, which may be run length encoded
+fn read_radiance_scanline(reader: &mut impl Read, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut first = [0u8; 4];
+    reader.read_exact(&mut first)?;
+
+    let is_rle = width >= 8 && width < 0x8000
+        && first[0] == 2 && first[1] == 2 && ((first[2] as usize) << 8 | first[3] as usize) == width;
+    if !is_rle {
+        // Flat pixels
+        scanline[0] = first;
+        for pixel in scanline[1..].iter_mut() {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    // Each channel is encoded separately as runs and literals
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8];
+            reader.read_exact(&mut count)?;
+            let count = count[0] as usize;
+            if count > 128 {
+                let run = count - 128;
+                if x + run > width {
+                    return Err(invalid_data("run overflows HDR scanline"));
+                }
+                let mut value = [0u8];
+                reader.read_exact(&mut value)?;
+                for pixel in scanline[x..x + run].iter_mut() {
+                    pixel[channel] = value[0];
+                }
+                x += run;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid_data("invalid literal in HDR scanline"));
+                }
+                let mut values = vec![0u8; count];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read a Radiance RGBE (.hdr) image
+pub fn read_radiance(reader: &mut impl BufRead) -> io::Result<HdrImage> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("not a Radiance HDR file"));
+    }
+
+    // Header variables until a blank line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("unexpected end of HDR header"));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(format!("unsupported HDR format {}", format)));
+            }
+        }
+    }
+
+    // Resolution, only the standard top to bottom, left to right orientation is supported
+    line.clear();
+    reader.read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<u32>().map_err(|_| invalid_data("invalid HDR height"))?,
+            w.parse::<u32>().map_err(|_| invalid_data("invalid HDR width"))?,
+        ),
+        _ => return Err(invalid_data(format!("unsupported HDR resolution line '{}'", line.trim()))),
+    };
+
+    let mut img = HdrImage::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width as usize];
+    for y in 0..height {
+        read_radiance_scanline(reader, &mut scanline)?;
+        for (x, rgbe) in scanline.iter().enumerate() {
+            img.put_pixel(x as u32, y, rgbe_to_rgb(*rgbe));
+        }
+    }
+    Ok(img)
+}
+
+/// Write a colour Portable Float Map
+pub fn write_pfm(writer: &mut impl Write, img: &HdrImage) -> io::Result<()> {
+    // A negative scale indicates little endian data
+    write!(writer, "PF\n{} {}\n-1.0\n", img.width(), img.height())?;
+    // Rows are stored bottom to top
+    for y in (0..img.height()).rev() {
+        for x in 0..img.width() {
+            for c in img.get_pixel(x, y).0.iter() {
+                writer.write_all(&c.to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+fn rgb_to_rgbe(rgb: &image::Rgb<f32>) -> [u8; 4] {
+    // NaN components are ignored by max, so an all NaN pixel is black
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    if !(max > 1e-32) {
+        return [0; 4];
+    }
+    // Saturate anything past the largest exponent, including infinity, to the largest value
+    if !max.is_finite() || max >= 2f32.powi(127) {
+        return [255; 4];
+    }
+    // Shared exponent such that the largest component has a mantissa in [128, 256)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(exponent);
+    let mantissa = |v: f32| (v.max(0.0) * scale).min(255.0) as u8;
+    [mantissa(rgb[0]), mantissa(rgb[1]), mantissa(rgb[2]), (exponent + 128).clamp(0, 255) as u8]
+}
+
+/// Write one scanline, run length encoded when the width allows it
+fn write_radiance_scanline(writer: &mut impl Write, scanline: &[[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    if width < 8 || width >= 0x8000 {
+        for pixel in scanline {
+            writer.write_all(pixel)?;
+        }
+        return Ok(());
+    }
+
+    writer.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+    for channel in 0..4 {
+        let values: Vec<u8> = scanline.iter().map(|p| p[channel]).collect();
+        let mut x = 0;
+        while x < width {
+            // Runs of at least 4 are worth encoding, otherwise gather literals up to the next run
+            let run = values[x..].iter().take(127).take_while(|&&v| v == values[x]).count();
+            if run >= 4 {
+                writer.write_all(&[128 + run as u8, values[x]])?;
+                x += run;
+                continue;
+            }
+            let mut end = x + 1;
+            while end < width && end - x < 128 {
+                if end + 3 < width && values[end..end + 4].iter().all(|&v| v == values[end]) {
+                    break;
+                }
+                end += 1;
+            }
+            writer.write_all(&[(end - x) as u8])?;
+            writer.write_all(&values[x..end])?;
+            x = end;
+        }
+    }
+    Ok(())
+}
+
+/// Write a Radiance RGBE (.hdr) image
+pub fn write_radiance(writer: &mut impl Write, img: &HdrImage) -> io::Result<()> {
+    write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", img.height(), img.width())?;
+    for y in 0..img.height() {
+        let scanline: Vec<[u8; 4]> = (0..img.width()).map(|x| rgb_to_rgbe(img.get_pixel(x, y))).collect();
+        write_radiance_scanline(writer, &scanline)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row wide enough for run length encoding, with special values and runs
+    fn test_image() -> HdrImage {
+        let values = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.5, 0.25],
+            [f32::INFINITY, 1.0, 0.0],
+            [f32::NAN, f32::NAN, f32::NAN],
+            [f32::NAN, 2.0, 0.0],
+            [1e-40, 0.0, 0.0],
+            [3e38, 3e38, 3e38],
+            [100.0, 100.0, 100.0],
+            [100.0, 100.0, 100.0],
+            [100.0, 100.0, 100.0],
+            [100.0, 100.0, 100.0],
+            [100.0, 100.0, 100.0],
+        ];
+        HdrImage::from_fn(values.len() as u32, 2, |x, y| {
+            let v = values[x as usize];
+            image::Rgb([v[0] * (y + 1) as f32, v[1], v[2]])
+        })
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let img = test_image();
+        let mut data = vec![];
+        write_pfm(&mut data, &img).unwrap();
+        let read = read_pfm(&mut &data[..]).unwrap();
+        assert_eq!((read.width(), read.height()), (img.width(), img.height()));
+        for (a, b) in img.pixels().zip(read.pixels()) {
+            for c in 0..3 {
+                assert!(a[c] == b[c] || (a[c].is_nan() && b[c].is_nan()), "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn radiance_round_trip() {
+        let img = test_image();
+        let mut data = vec![];
+        write_radiance(&mut data, &img).unwrap();
+        let read = read_radiance(&mut &data[..]).unwrap();
+        assert_eq!((read.width(), read.height()), (img.width(), img.height()));
+        for (a, b) in img.pixels().zip(read.pixels()) {
+            for c in 0..3 {
+                assert!(b[c].is_finite() && b[c] >= 0.0, "{:?} read as {:?}", a, b);
+                if a[c].is_finite() && a[c] < 1e38 {
+                    // The shared exponent loses precision in components much smaller than the largest
+                    let max = a[0].max(a[1]).max(a[2]);
+                    assert!((a[c] - b[c]).abs() <= 0.01 * max + 1e-32, "{:?} read as {:?}", a, b);
+                }
+            }
+        }
+        // Infinity saturates rather than wrapping around to something small
+        assert!(read.get_pixel(2, 0)[0] > 1e38);
+        // All NaN pixels are black
+        assert_eq!(read.get_pixel(3, 0).0, [0.0; 3]);
+    }
+}
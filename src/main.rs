@@ -1,19 +1,28 @@
+mod aov;
+mod blue_noise;
 mod camera;
+mod denoise;
+mod filter;
+mod hdr;
 mod material;
+mod obj;
 mod object;
 mod render;
+mod sampler;
 mod scene;
+mod scenefile;
+mod scenes;
 mod server;
 mod shared;
+mod texture;
+mod tonemap;
 
 use std::path::PathBuf;
-use rand::{Rng, SeedableRng};
 
-use camera::Camera;
-use material::{Dielectric, Lambertian, Material, Metal};
-use object::Sphere;
-use scene::Scene;
-use shared::{Color, Point3, Vec3, color_random, color_random_range};
+use aov::Aov;
+use scenefile::SceneDescription;
+use scenes::BuiltinScene;
+use shared::Color;
 use structopt::StructOpt;
 
 mod parallel {
@@ -74,111 +83,15 @@ mod parallel {
     }
 }
 
-fn one_weekend_cam(width: usize, height: usize) -> Camera {
-    one_weekend_cam_lookat(width, height, Point3::new(0.0, 0.0, 0.0))
-}
-
-fn one_weekend_cam_lookat(width: usize, height: usize, lookat: Point3) -> Camera {
-    let aspect_ratio = (width as f32) / (height as f32);
-
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-
-    Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        20.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    )
-}
-
-/// Generate the ray tracing in one weekend scene
-fn one_weekend_scene() -> Scene {
-    let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
-    let mut scene = Scene::new();
-
-    let mut spheres: Vec<(Point3, f32)> = Vec::new();
-    let mut add_sphere =
-        |spheres: &mut Vec<(Point3, f32)>, c: Point3, r: f32, mat: Material| {
-            scene.objects.push(Sphere::new(c, r, mat.clone()));
-            spheres.push((c, r));
-        };
-
-    let sphere_intersects = |spheres: &Vec<(Point3, f32)>, c: Point3, r: f32| {
-        spheres.iter().any(|s| (s.0 - c).length() < (s.1 + r))
-    };
-
-    let ground_material: Material = Material::Lambertian(Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5),
-    });
-    add_sphere(
-        &mut spheres,
-        Point3::new(0.0, -1000.0, -1.0),
-        1000.0,
-        ground_material,
-    );
-
-    let material1: Material = Material::Dielectric(Dielectric { ir: 1.5 });
-    add_sphere(&mut spheres, Point3::new(0.0, 1.0, 0.0), 1.0, material1);
-
-    let material2: Material = Material::Lambertian(Lambertian {
-        albedo: Color::new(0.4, 0.2, 0.1),
-    });
-    add_sphere(&mut spheres, Point3::new(-4.0, 1.0, 0.0), 1.0, material2);
-
-    let material3: Material = Material::Metal(Metal {
-        albedo: Color::new(0.7, 0.6, 0.5),
-        fuzz: 0.0,
-    });
-    add_sphere(&mut spheres, Point3::new(4.0, 1.0, 0.0), 1.0, material3);
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen_range(0.0..1.0);
-            let mut center;
-            // Find a position which doesn't intersect with any other sphere
-            loop {
-                center = Point3::new(
-                    a as f32 + 0.9 * rng.gen_range(0.0..1.0),
-                    0.2,
-                    b as f32 + 0.9 * rng.gen_range(0.0..1.0),
-                );
-                if !sphere_intersects(&spheres, center, 0.2) {
-                    break;
-                }
-            }
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.7 {
-                    // diffuse
-                    let albedo = color_random(&mut rng);
-                    let sphere_material: Material =
-                        Material::Lambertian(Lambertian { albedo: albedo });
-                    add_sphere(&mut spheres, center, 0.2, sphere_material);
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = color_random_range(&mut rng, 0.5, 1.0);
-                    let fuzz = rng.gen_range(0.0..0.5);
-                    let sphere_material: Material = Material::Metal(Metal {
-                        albedo: albedo,
-                        fuzz: fuzz,
-                    });
-                    add_sphere(&mut spheres, center, 0.2, sphere_material);
-                } else {
-                    // glass
-                    let sphere_material: Material = Material::Dielectric(Dielectric { ir: 1.5 });
-                    add_sphere(&mut spheres, center, 0.2, sphere_material);
-                }
-            }
-        }
+/// Load the scene file at path, or else the named built in scene
+fn load_scene(path: Option<PathBuf>, builtin: BuiltinScene) -> SceneDescription {
+    match path {
+        Some(path) => scenefile::load(&path).unwrap_or_else(|e| {
+            println!("failed to load scene: {}", e);
+            std::process::exit(1);
+        }),
+        None => builtin.description(),
     }
-
-    return scene;
 }
 
 #[derive(Debug, StructOpt)]
@@ -190,11 +103,22 @@ struct Opt {
 #[derive(Debug, StructOpt)]
 enum Cmd {
     #[structopt(about = "start a web server with a rendering control panel on 0.0.0.0:28888")]
-    Serve,
+    Serve {
+        #[structopt(long, help = "JSON scene file to render instead of a built in scene")]
+        scene: Option<PathBuf>,
+        #[structopt(long, default_value = "one-weekend", help = "built in scene to render without a scene file (one-weekend, cornell-box, next-week, furnace, many-lights, glass-caustics)")]
+        builtin: BuiltinScene,
+    },
     #[structopt(about = "render an X11 window with a single frame being processed in parallel in blocks")]
     Window {
-        #[structopt(long)]
+        #[structopt(long, help = "image to write once rendered - .hdr and .pfm keep the full dynamic range")]
         out_file: Option<PathBuf>,
+        #[structopt(long, use_delimiter = true, help = "AOVs (depth, normal, albedo, object-id, sample-count) to write alongside out_file, e.g. out.depth.pfm")]
+        aovs: Vec<Aov>,
+        #[structopt(long, help = "JSON scene file to render instead of a built in scene")]
+        scene: Option<PathBuf>,
+        #[structopt(long, default_value = "one-weekend", help = "built in scene to render without a scene file (one-weekend, cornell-box, next-week, furnace, many-lights, glass-caustics)")]
+        builtin: BuiltinScene,
     },
     #[structopt(about = "perform some size analysis, useful for assessing how much data may move over the wire")]
     SizeAnalyze,
@@ -213,11 +137,13 @@ fn main() {
     //let cpus = 14;
 
     match opt.cmd {
-        Cmd::Serve => {
-            server::main("0.0.0.0:28888".to_owned(), cpus)
+        Cmd::Serve { scene, builtin } => {
+            // The server can switch to other built in scenes, so it needs to know which this is
+            let builtin_shown = if scene.is_some() { None } else { Some(builtin) };
+            server::main("0.0.0.0:28888".to_owned(), cpus, load_scene(scene, builtin), builtin_shown)
         },
-        Cmd::Window { out_file } => {
-            window::main(out_file, cpus)
+        Cmd::Window { out_file, aovs, scene, builtin } => {
+            window::main(out_file, aovs, cpus, load_scene(scene, builtin))
         },
         Cmd::SizeAnalyze => {
             let width = 1280/4;
@@ -226,7 +152,8 @@ fn main() {
 
             let block_size = 32;
 
-            const CHANNELS: usize = 3;
+            // Linear float RGB
+            const BYTES_PER_PIXEL: usize = 3 * std::mem::size_of::<f32>();
             const UNIT_BOUND: usize = 1024;
 
             fn sizefmt(mut n: usize) -> String {
@@ -243,7 +170,7 @@ fn main() {
 
             println!("# IMAGE");
             println!("Considering an image of {}px x {}px with {} frames", width, height, frames);
-            let frame_bytes = width * height * CHANNELS;
+            let frame_bytes = width * height * BYTES_PER_PIXEL;
             println!("{} for pixels for a single uncompressed frame", sizefmt(frame_bytes));
             println!("{} for all {} frames", sizefmt(frame_bytes * frames), frames);
             println!("");
@@ -257,8 +184,8 @@ fn main() {
             println!("");
 
             println!("# JOB");
-            let scene = one_weekend_scene();
-            let cam = one_weekend_cam(width, height);
+            let scene = scenes::one_weekend_scene();
+            let cam = scenes::one_weekend_cam_settings().build(width, height);
             let scene_bytes = bincode::serialized_size(&scene).unwrap() as usize;
             let cam_bytes = bincode::serialized_size(&cam).unwrap() as usize;
             let job_bytes = scene_bytes + cam_bytes;
@@ -287,7 +214,10 @@ mod window {
     use std::path::PathBuf;
     use std::process;
 
-    pub fn main(_out_file: Option<PathBuf>, _cpus: usize) {
+    use crate::aov::Aov;
+    use crate::scenefile::SceneDescription;
+
+    pub fn main(_out_file: Option<PathBuf>, _aovs: Vec<Aov>, _cpus: usize, _desc: SceneDescription) {
         println!("gui support not compiled in - please recompile with 'gui' feature");
         process::exit(1);
     }
@@ -297,28 +227,16 @@ mod window {
 mod window {
     use futures::prelude::*;
     use minifb::{Key, Window, WindowOptions};
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
+    use crate::aov::Aov;
+    use crate::hdr;
     use crate::parallel;
     use crate::render;
-    use crate::{one_weekend_cam, one_weekend_scene};
+    use crate::scenefile::SceneDescription;
 
     type ColorDisplay = u32;
 
-    fn u8_vec_from_buffer_display(bd: &[ColorDisplay]) -> Vec<u8> {
-        bd
-            .into_iter()
-            .flat_map(|x| u8_vec_from_color_display(*x))
-            .collect()
-    }
-
-    fn u8_vec_from_color_display(c: ColorDisplay) -> [u8; 3] {
-        let b = c as u8;
-        let g = (c >> 8) as u8;
-        let r = (c >> 16) as u8;
-        return [r, g, b];
-    }
-
     fn color_display_from_rgb(rgb: image::Rgb<u8>) -> ColorDisplay {
         let (r, g, b) = (rgb[0] as u32, rgb[1] as u32, rgb[2] as u32);
         (r << 16) | (g << 8) | b
@@ -328,18 +246,30 @@ mod window {
         (y * image_width + x) as usize
     }
 
-    pub fn main(out_file: Option<PathBuf>, cpus: usize) {
-        const WIDTH: usize = 1280;
-        const HEIGHT: usize = 720;
-        const SAMPLES_PER_PIXEL: u32 = 128;
+    /// Path for an AOV next to the main output, e.g. out.depth.pfm for out.pfm
+    fn aov_path(out_file: &Path, aov: Aov) -> PathBuf {
+        let stem = out_file.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match out_file.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, aov, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, aov),
+        };
+        out_file.with_file_name(name)
+    }
+
+    pub fn main(out_file: Option<PathBuf>, aovs: Vec<Aov>, cpus: usize, desc: SceneDescription) {
+        let width = desc.render.width.unwrap_or(1280) as usize;
+        let height = desc.render.height.unwrap_or(720) as usize;
+        let samples_per_pixel = desc.render.samples_per_pixel.unwrap_or(128);
+        let tonemap = desc.render.tonemap.unwrap_or_default();
+        let denoiser = desc.render.denoise;
 
         #[cfg(feature = "distributed")]
         std::env::set_var("DISPLAY", ":0"); // hack around hadean environment variables for local runs
 
         let mut window = Window::new(
             "Ray tracing in one weekend - ESC to exit",
-            WIDTH,
-            HEIGHT,
+            width,
+            height,
             WindowOptions::default(),
         )
         .unwrap_or_else(|e| {
@@ -349,14 +279,37 @@ mod window {
         // Limit to max ~60 fps update rate
         window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
 
-        let mut scene = one_weekend_scene();
+        let mut scene = desc.scene;
         scene.build_bvh();
-        let cam = one_weekend_cam(WIDTH, HEIGHT);
+        let cam = desc.camera.build(width, height);
 
-        let render_worker =
-            render::Renderer::new(WIDTH as u32, HEIGHT as u32, SAMPLES_PER_PIXEL, scene, cam);
+        let mut render_worker =
+            render::Renderer::new(width as u32, height as u32, samples_per_pixel, scene, cam);
+        if let Some(max_depth) = desc.render.max_depth {
+            render_worker.set_max_depth(max_depth);
+        }
+        if let Some(roulette_depth) = desc.render.roulette_depth {
+            render_worker.set_roulette_depth(roulette_depth);
+        }
+        render_worker.set_adaptive(desc.render.adaptive());
+        render_worker.set_progressive(desc.render.pass_samples);
+        render_worker.set_sampler(desc.render.sampler.unwrap_or_default());
+        render_worker.set_filter(desc.render.filter.unwrap_or_default());
+        // The denoiser is guided by albedo and normals, so those are rendered even if not written
+        let mut render_aovs = aovs.clone();
+        if denoiser.is_some() {
+            for aov in [Aov::Albedo, Aov::Normal] {
+                if !render_aovs.contains(&aov) {
+                    render_aovs.push(aov);
+                }
+            }
+        }
+        render_worker.set_aovs(&render_aovs);
 
-        let mut buffer_display = vec![0; WIDTH * HEIGHT];
+        let mut buffer_display = vec![0; width * height];
+        // Linear radiance and AOVs for writing the output
+        let mut frame = render::RenderOutput::new(width as u32, height as u32, &render_aovs);
+        let mut finished = false;
 
         let mut pool = parallel::default_pool(cpus);
 
@@ -378,19 +331,34 @@ mod window {
 
             while window.is_open() && !window.is_key_down(Key::Escape) {
                 let has_changed = match rx.try_recv() {
-                    Ok((renderblock, result_img)) => {
-                        for (px, py, pixel) in result_img.enumerate_pixels() {
-                            let index = index_from_xy(WIDTH as u32, HEIGHT as u32, renderblock.x + px, renderblock.y + py);
+                    Ok((renderblock, result)) => {
+                        frame.copy_from(&result, renderblock.x, renderblock.y);
+                        for (px, py, pixel) in tonemap.apply(&result.beauty).enumerate_pixels() {
+                            let index = index_from_xy(width as u32, height as u32, renderblock.x + px, renderblock.y + py);
                             buffer_display[index] = color_display_from_rgb(*pixel);
                         }
                         true
                     },
+                    // All blocks are in, denoise the whole image if asked to
+                    Err(crossbeam::channel::TryRecvError::Disconnected) if !finished => {
+                        finished = true;
+                        match denoiser {
+                            Some(denoiser) => {
+                                frame.beauty = denoiser.apply(&frame);
+                                for (pixel, display) in tonemap.apply(&frame.beauty).pixels().zip(buffer_display.iter_mut()) {
+                                    *display = color_display_from_rgb(*pixel);
+                                }
+                                true
+                            },
+                            None => false,
+                        }
+                    },
                     Err(crossbeam::channel::TryRecvError::Empty) |
                     Err(crossbeam::channel::TryRecvError::Disconnected) => false,
                 };
                 if has_changed {
                     window
-                        .update_with_buffer(&buffer_display, WIDTH, HEIGHT)
+                        .update_with_buffer(&buffer_display, width, height)
                         .unwrap();
                 } else {
                     window.update();
@@ -399,11 +367,12 @@ mod window {
         })
         .unwrap();
 
-        // If we get one argument, assume it's our output png filename
+        // Write the output, as HDR for .hdr and .pfm files
         if let Some(out_file) = out_file {
-            let pixels = u8_vec_from_buffer_display(&buffer_display);
-            let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_raw(WIDTH as u32, HEIGHT as u32, pixels).unwrap());
-            img.save(out_file).unwrap();
+            hdr::save(&out_file, &frame.beauty, &tonemap).unwrap();
+            for (aov, img) in frame.aovs.iter().filter(|(aov, _)| aovs.contains(aov)) {
+                hdr::save_with(&aov_path(&out_file, *aov), img, |img| aov.visualize(img)).unwrap();
+            }
         }
     }
 }
@@ -2,7 +2,8 @@ use rand::Rng;
 use serde::{Serialize, Deserialize};
 
 use crate::object::HitRecord;
-use crate::shared::{Color, Ray, Vec3, VecExt, random_in_unit_sphere, reflectance, random_unit_vector, vec_reflect, vec_refract};
+use crate::shared::{Color, Ray, Vec3, VecExt, orthonormal_basis, random_in_unit_sphere, reflectance, random_unit_vector, vec_reflect, vec_refract};
+use crate::texture::{ImageTexture, Texture};
 
 /// A material which can scatter rays
 #[derive(Copy, Clone)]
@@ -11,18 +12,79 @@ pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
+    DiffuseLight(DiffuseLight),
+    MetallicRoughness(MetallicRoughness),
+    Medium(Medium),
 }
 
+/// Materials look up their textures with the scene's image table, `images`
 impl Material {
-    pub fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterResult> {
+    pub fn scatter<T: Rng>(&self, ray: &Ray, hit: &HitRecord, images: &[ImageTexture], rng: &mut T) -> Option<ScatterResult> {
         match self {
-            Material::Lambertian(m) => m.scatter(ray, hit),
-            Material::Metal(m) => m.scatter(ray, hit),
-            Material::Dielectric(m) => m.scatter(ray, hit),
+            Material::Lambertian(m) => m.scatter(ray, hit, images, rng),
+            Material::Metal(m) => m.scatter(ray, hit, images, rng),
+            Material::Dielectric(m) => m.scatter(ray, hit, rng),
+            Material::DiffuseLight(_) => None,
+            Material::MetallicRoughness(m) => m.scatter(ray, hit, images, rng),
+            Material::Medium(m) => m.scatter(ray, hit, images, rng),
+        }
+    }
+
+    /// Whether scattering is too concentrated for sampling directions towards lights to be useful
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Lambertian(_) | Material::MetallicRoughness(_) | Material::Medium(_) => false,
+            _ => true,
+        }
+    }
+
+    /// BSDF value multiplied by the cosine term for light arriving from direction, where ray is the
+    /// ray which made the hit
+    pub fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3, images: &[ImageTexture]) -> Color {
+        match self {
+            Material::Lambertian(m) => m.albedo.value(hit, images) * cosine_pdf(hit.normal, direction),
+            Material::MetallicRoughness(m) => m.eval(ray, hit, direction, images),
+            Material::Medium(m) => m.albedo.value(hit, images) * m.phase(ray, direction),
+            _ => Color::ZERO,
+        }
+    }
+
+    /// Solid angle density with which scatter produces direction
+    pub fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: Vec3, images: &[ImageTexture]) -> f32 {
+        match self {
+            Material::Lambertian(_) => cosine_pdf(hit.normal, direction),
+            Material::MetallicRoughness(m) => m.pdf(ray, hit, direction, images),
+            Material::Medium(m) => m.phase(ray, direction),
+            _ => 0.0,
+        }
+    }
+
+    /// Radiance emitted from the hit point towards the ray origin
+    pub fn emitted(&self, hit: &HitRecord) -> Color {
+        match self {
+            Material::DiffuseLight(m) => m.emitted(hit),
+            _ => Color::ZERO,
+        }
+    }
+
+    /// Overall reflectance, for the albedo AOV
+    pub fn albedo(&self, hit: &HitRecord, images: &[ImageTexture]) -> Color {
+        match self {
+            Material::Lambertian(m) => m.albedo.value(hit, images),
+            Material::Metal(m) => m.albedo.value(hit, images),
+            Material::Dielectric(_) => Color::ONE,
+            Material::DiffuseLight(m) => m.emit.min(Color::ONE),
+            Material::MetallicRoughness(m) => m.base_color.value(hit, images),
+            Material::Medium(m) => m.albedo.value(hit, images),
         }
     }
 }
 
+/// Density of a cosine weighted distribution around normal
+fn cosine_pdf(normal: Vec3, direction: Vec3) -> f32 {
+    f32::max(normal.dot(direction.normalize()), 0.0) / std::f32::consts::PI
+}
+
 /// Result of Material::scatter
 pub struct ScatterResult {
     pub attenuation: Color,
@@ -32,19 +94,19 @@ pub struct ScatterResult {
 #[derive(Copy, Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Texture,
 }
 
 impl Lambertian {
-    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterResult> {
-        let mut scatter_direction = hit.normal + random_unit_vector();
+    fn scatter<T: Rng>(&self, ray: &Ray, hit: &HitRecord, images: &[ImageTexture], rng: &mut T) -> Option<ScatterResult> {
+        let mut scatter_direction = hit.normal + random_unit_vector(rng);
         if scatter_direction.near_zero() {
             scatter_direction = hit.normal;
         }
 
-        let scattered = Ray::new(hit.point, scatter_direction);
+        let scattered = Ray::new(hit.point, scatter_direction, ray.time);
         Some(ScatterResult {
-            attenuation: self.albedo,
+            attenuation: self.albedo.value(hit, images),
             scattered_ray: scattered,
         })
     }
@@ -53,17 +115,17 @@ impl Lambertian {
 #[derive(Copy, Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: Texture,
     pub fuzz: f32,
 }
 
 impl Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterResult> {
+    fn scatter<T: Rng>(&self, ray: &Ray, hit: &HitRecord, images: &[ImageTexture], rng: &mut T) -> Option<ScatterResult> {
         let reflected = vec_reflect(ray.direction.normalize(), hit.normal);
 
-        let scattered = Ray::new(hit.point, reflected + self.fuzz * random_in_unit_sphere());
+        let scattered = Ray::new(hit.point, reflected + self.fuzz * random_in_unit_sphere(rng), ray.time);
         Some(ScatterResult {
-            attenuation: self.albedo,
+            attenuation: self.albedo.value(hit, images),
             scattered_ray: scattered,
         })
     }
@@ -76,9 +138,7 @@ pub struct Dielectric {
 }
 
 impl Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterResult> {
-        let mut rng = rand::thread_rng();
-
+    fn scatter<T: Rng>(&self, ray: &Ray, hit: &HitRecord, rng: &mut T) -> Option<ScatterResult> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let refraction_ratio = if hit.front_face {
             1.0 / self.ir
@@ -98,10 +158,244 @@ impl Dielectric {
             direction = vec_refract(unit_direction, hit.normal, refraction_ratio);
         }
 
-        let scattered = Ray::new(hit.point, direction);
+        let scattered = Ray::new(hit.point, direction, ray.time);
         Some(ScatterResult {
             attenuation: attenuation,
             scattered_ray: scattered,
         })
     }
 }
+
+/// An emitter which radiates equally in all directions from the front face
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    fn emitted(&self, hit: &HitRecord) -> Color {
+        if hit.front_face {
+            self.emit
+        } else {
+            Color::ZERO
+        }
+    }
+}
+
+// Reflectance at normal incidence of dielectrics in the metallic-roughness model, i.e. an IOR of 1.5
+const DIELECTRIC_F0: f32 = 0.04;
+
+// Smallest GGX alpha, as a perfectly smooth surface can't be evaluated
+const MIN_GGX_ALPHA: f32 = 1e-3;
+
+/// Physically based material following glTF's metallic-roughness model. A GGX (Trowbridge-Reitz)
+/// microfacet specular lobe with Schlick Fresnel is layered over a Lambertian base, with metals
+/// tinting the specular by base_color and having no diffuse.
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct MetallicRoughness {
+    pub base_color: Texture,
+    // In [0, 1], where 0 is a dielectric and 1 a metal
+    pub metallic: f32,
+    // Perceptual roughness in [0, 1], the GGX alpha is its square
+    pub roughness: f32,
+}
+
+/// Parameters of a MetallicRoughness at a hit, in the local frame where the normal is +Z
+struct MicrofacetFrame {
+    frame: (Vec3, Vec3, Vec3),
+    // Direction towards the viewer
+    wo: Vec3,
+    alpha: f32,
+    diffuse: Color,
+    f0: Color,
+    // Chance of sampling the specular lobe rather than the diffuse one
+    specular_probability: f32,
+}
+
+impl MicrofacetFrame {
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        let (u, v_axis, w) = self.frame;
+        Vec3::new(v.dot(u), v.dot(v_axis), v.dot(w))
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        let (u, v_axis, w) = self.frame;
+        v.x * u + v.y * v_axis + v.z * w
+    }
+
+    /// GGX normal distribution
+    fn d(&self, h: Vec3) -> f32 {
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.0) + 1.0;
+        a2 / (std::f32::consts::PI * t * t)
+    }
+
+    /// Smith's Lambda for GGX, with the masking function G1 = 1 / (1 + lambda)
+    fn lambda(&self, v: Vec3) -> f32 {
+        let cos2 = v.z * v.z;
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Color {
+        self.f0 + (Color::ONE - self.f0) * (1.0 - cos_theta).max(0.0).powi(5)
+    }
+
+    /// BSDF times cosine, and the density of sampling wi, for a local direction wi
+    fn eval_pdf(&self, wi: Vec3) -> (Color, f32) {
+        let wo = self.wo;
+        if wi.z <= 0.0 {
+            return (Color::ZERO, 0.0);
+        }
+        let h = (wo + wi).normalize();
+        let d = self.d(h);
+        let f = self.fresnel(wo.dot(h));
+        // Height correlated masking and shadowing
+        let g2 = 1.0 / (1.0 + self.lambda(wo) + self.lambda(wi));
+        let specular = f * d * g2 / (4.0 * wo.z);
+        // glTF weights the diffuse lobe by the Fresnel of each microfacet, which gains energy at
+        // grazing angles, so the light reflected at the viewing angle is taken out instead
+        let diffuse = (Color::ONE - self.fresnel(wo.z)) * self.diffuse * wi.z / std::f32::consts::PI;
+
+        // Visible normals are sampled, with the density of the reflected direction being
+        // G1(wo) D(h) / (4 wo.z)
+        let g1 = 1.0 / (1.0 + self.lambda(wo));
+        let specular_pdf = g1 * d / (4.0 * wo.z);
+        let diffuse_pdf = wi.z / std::f32::consts::PI;
+        let pdf = self.specular_probability * specular_pdf + (1.0 - self.specular_probability) * diffuse_pdf;
+        (specular + diffuse, pdf)
+    }
+
+    /// Sample a microfacet normal from the distribution of normals visible from wo, using Heitz's
+    /// "Sampling the GGX Distribution of Visible Normals"
+    fn sample_visible_normal(&self, u1: f32, u2: f32) -> Vec3 {
+        let wo = self.wo;
+        let alpha = self.alpha;
+        // Stretch to the hemisphere configuration
+        let vh = Vec3::new(alpha * wo.x, alpha * wo.y, wo.z).normalize();
+        let len_sq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len_sq > 0.0 { Vec3::new(-vh.y, vh.x, 0.0) / len_sq.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = vh.cross(t1);
+        // Point on the projected disk
+        let r = u1.sqrt();
+        let phi = 2.0 * std::f32::consts::PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        // Unstretch
+        Vec3::new(alpha * nh.x, alpha * nh.y, nh.z.max(0.0)).normalize()
+    }
+}
+
+impl MetallicRoughness {
+    fn frame(&self, ray: &Ray, hit: &HitRecord, images: &[ImageTexture]) -> Option<MicrofacetFrame> {
+        let base_color = self.base_color.value(hit, images);
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        let roughness = self.roughness.clamp(0.0, 1.0);
+        let mut frame = MicrofacetFrame {
+            frame: orthonormal_basis(hit.normal),
+            wo: Vec3::ZERO,
+            alpha: (roughness * roughness).max(MIN_GGX_ALPHA),
+            diffuse: base_color * (1.0 - metallic),
+            f0: Color::splat(DIELECTRIC_F0) * (1.0 - metallic) + base_color * metallic,
+            specular_probability: 0.0,
+        };
+        frame.wo = frame.to_local(-ray.direction.normalize());
+        // Shading normals can face away from the viewer
+        if frame.wo.z <= 0.0 {
+            return None;
+        }
+        // Sample the lobes in proportion to their reflectance at the viewing angle
+        let specular = frame.fresnel(frame.wo.z).max_element();
+        let diffuse = frame.diffuse.max_element() * (1.0 - specular);
+        frame.specular_probability = if specular + diffuse > 0.0 {
+            (specular / (specular + diffuse)).clamp(0.1, 1.0)
+        } else {
+            1.0
+        };
+        Some(frame)
+    }
+
+    fn scatter<T: Rng>(&self, ray: &Ray, hit: &HitRecord, images: &[ImageTexture], rng: &mut T) -> Option<ScatterResult> {
+        let frame = self.frame(ray, hit, images)?;
+        let wi = if rng.gen_range(0.0..1.0) < frame.specular_probability {
+            let h = frame.sample_visible_normal(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+            vec_reflect(-frame.wo, h)
+        } else {
+            // Cosine weighted
+            let d = random_unit_vector(rng);
+            let d = Vec3::new(d.x, d.y, d.z + 1.0);
+            if d.near_zero() { Vec3::Z } else { d.normalize() }
+        };
+
+        let (f, pdf) = frame.eval_pdf(wi);
+        if !(pdf > 0.0) {
+            return None;
+        }
+        Some(ScatterResult {
+            attenuation: f / pdf,
+            scattered_ray: Ray::new(hit.point, frame.to_world(wi), ray.time),
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3, images: &[ImageTexture]) -> Color {
+        match self.frame(ray, hit, images) {
+            Some(frame) => frame.eval_pdf(frame.to_local(direction.normalize())).0,
+            None => Color::ZERO,
+        }
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: Vec3, images: &[ImageTexture]) -> f32 {
+        match self.frame(ray, hit, images) {
+            Some(frame) => frame.eval_pdf(frame.to_local(direction.normalize())).1,
+            None => 0.0,
+        }
+    }
+}
+
+/// Scattering inside a participating medium, such as smoke or fog, with the Henyey-Greenstein phase
+/// function. This is only meaningful for the hits of a ConstantMedium or the scene's fog.
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Medium {
+    // Fraction of the light scattered rather than absorbed at each event
+    pub albedo: Texture,
+    // Mean cosine of the scattering angle in (-1, 1), where 0 is isotropic, positive scatters
+    // forwards and negative backwards
+    pub anisotropy: f32,
+}
+
+impl Medium {
+    /// Henyey-Greenstein density of scattering from the direction of ray into direction
+    fn phase(&self, ray: &Ray, direction: Vec3) -> f32 {
+        let g = self.anisotropy;
+        let cos_theta = ray.direction.normalize().dot(direction.normalize());
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * std::f32::consts::PI * denom * denom.sqrt())
+    }
+
+    fn scatter<T: Rng>(&self, ray: &Ray, hit: &HitRecord, images: &[ImageTexture], rng: &mut T) -> Option<ScatterResult> {
+        let g = self.anisotropy;
+        let u: f32 = rng.gen_range(0.0..1.0);
+        // Invert the cumulative distribution of the scattering angle
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * rng.gen_range(0.0..1.0);
+        let (u, v, w) = orthonormal_basis(ray.direction.normalize());
+        let direction = u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta;
+
+        // The direction is sampled exactly by the phase function, so only the albedo remains
+        Some(ScatterResult {
+            attenuation: self.albedo.value(hit, images),
+            scattered_ray: Ray::new(hit.point, direction, ray.time),
+        })
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, MetallicRoughness};
+use crate::object::{Object, TriangleMesh};
+use crate::shared::{Color, Point3, Vec2, Vec3};
+use crate::texture::Texture;
+
+/// Error from loading an OBJ or MTL file
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ObjError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Material parameters from an MTL file which we know how to map
+#[derive(Clone)]
+struct MtlMaterial {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f32,
+    ior: f32,
+    dissolve: f32,
+    illum: u32,
+    // From the PBR extension to MTL, either of which makes it a metallic-roughness material
+    roughness: Option<f32>,
+    metallic: Option<f32>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::ZERO,
+            emission: Color::ZERO,
+            shininess: 0.0,
+            ior: 1.0,
+            dissolve: 1.0,
+            illum: 2,
+            roughness: None,
+            metallic: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// Pick the closest renderer material. Emissive materials become lights, transparent materials
+    /// become dielectrics, materials with PBR parameters become metallic-roughness, materials which
+    /// are more specular than diffuse become metals with a fuzz derived from the Phong exponent, and
+    /// everything else is Lambertian.
+    fn to_material(&self) -> Material {
+        if self.emission.max_element() > 0.0 {
+            return Material::DiffuseLight(DiffuseLight { emit: self.emission });
+        }
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        if transparent {
+            let ir = if self.ior > 1.0 { self.ior } else { 1.5 };
+            return Material::Dielectric(Dielectric { ir: ir });
+        }
+        if self.roughness.is_some() || self.metallic.is_some() {
+            // An unset Pm means a dielectric as in the PBR extension to MTL, unlike glTF's default
+            return Material::MetallicRoughness(MetallicRoughness {
+                base_color: Texture::Solid(self.diffuse),
+                metallic: self.metallic.unwrap_or(0.0).clamp(0.0, 1.0),
+                roughness: self.roughness.unwrap_or(1.0).clamp(0.0, 1.0),
+            });
+        }
+        if self.specular.max_element() > self.diffuse.max_element() {
+            let fuzz = f32::sqrt(2.0 / (self.shininess.max(0.0) + 2.0));
+            return Material::Metal(Metal {
+                albedo: Texture::Solid(self.specular),
+                fuzz: fuzz.min(1.0),
+            });
+        }
+        Material::Lambertian(Lambertian { albedo: Texture::Solid(self.diffuse) })
+    }
+}
+
+/// Tracks the current file and line for error reporting
+struct Cursor<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn error(&self, message: impl Into<String>) -> ObjError {
+        ObjError::Parse { path: self.path.to_owned(), line: self.line, message: message.into() }
+    }
+
+    fn floats<const N: usize>(&self, args: &[&str]) -> Result<[f32; N], ObjError> {
+        if args.len() < N {
+            return Err(self.error(format!("expected {} numbers, found {}", N, args.len())));
+        }
+        let mut out = [0.0; N];
+        for (o, a) in out.iter_mut().zip(args) {
+            *o = a.parse().map_err(|_| self.error(format!("invalid number '{}'", a)))?;
+        }
+        Ok(out)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_owned(), e))
+}
+
+/// Parse an MTL file into named materials
+fn load_mtl(path: &Path, materials: &mut HashMap<String, MtlMaterial>) -> Result<(), ObjError> {
+    let src = read_file(path)?;
+    let mut cursor = Cursor { path, line: 0 };
+    let mut current: Option<String> = None;
+
+    for (line_index, line) in src.lines().enumerate() {
+        cursor.line = line_index + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            let name = args.join(" ");
+            materials.insert(name.clone(), MtlMaterial::default());
+            current = Some(name);
+            continue;
+        }
+
+        let mtl = match current.as_ref().and_then(|name| materials.get_mut(name)) {
+            Some(mtl) => mtl,
+            None => return Err(cursor.error(format!("'{}' before any newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => mtl.diffuse = Color::from(cursor.floats::<3>(&args)?),
+            "Ks" => mtl.specular = Color::from(cursor.floats::<3>(&args)?),
+            "Ke" => mtl.emission = Color::from(cursor.floats::<3>(&args)?),
+            "Ns" => mtl.shininess = cursor.floats::<1>(&args)?[0],
+            "Ni" => mtl.ior = cursor.floats::<1>(&args)?[0],
+            "d" => mtl.dissolve = cursor.floats::<1>(&args)?[0],
+            "Tr" => mtl.dissolve = 1.0 - cursor.floats::<1>(&args)?[0],
+            "Pr" => mtl.roughness = Some(cursor.floats::<1>(&args)?[0]),
+            "Pm" => mtl.metallic = Some(cursor.floats::<1>(&args)?[0]),
+            "illum" => {
+                mtl.illum = args.first()
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| cursor.error("invalid illum"))?
+            },
+            // Texture maps and other parameters aren't supported
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Triangles for a single material, with vertices de-indexed from the separate OBJ index spaces
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    indices: Vec<[u32; 3]>,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    missing_normals: bool,
+    missing_uvs: bool,
+}
+
+/// Resolve a 1-based (or negative, relative to the end) OBJ index
+fn resolve_index(cursor: &Cursor, s: &str, len: usize) -> Result<usize, ObjError> {
+    let i: i64 = s.parse().map_err(|_| cursor.error(format!("invalid index '{}'", s)))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(cursor.error(format!("index {} out of range", i)));
+    }
+    Ok(resolved as usize)
+}
+
+/// Load a Wavefront OBJ file, producing a triangle mesh per material group. Materials come from
+/// any referenced MTL files, with `default_material` used for faces without a known material.
+pub fn load_obj(path: &Path, default_material: Material) -> Result<Vec<Object>, ObjError> {
+    let src = read_file(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut cursor = Cursor { path, line: 0 };
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Vec2> = Vec::new();
+    let mut mtl_materials: HashMap<String, MtlMaterial> = HashMap::new();
+
+    // Meshes in order of first use, keyed by material name
+    let mut meshes: Vec<(Option<String>, MeshBuilder)> = vec![(None, MeshBuilder::default())];
+    let mut current_mesh = 0;
+
+    for (line_index, line) in src.lines().enumerate() {
+        cursor.line = line_index + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => positions.push(Point3::from(cursor.floats::<3>(&args)?)),
+            // Exporters sometimes write zero normals, which are left for the face normal to replace
+            "vn" => normals.push(Vec3::from(cursor.floats::<3>(&args)?).normalize_or_zero()),
+            "vt" => uvs.push(Vec2::from(cursor.floats::<2>(&args)?)),
+            "mtllib" => {
+                for name in args {
+                    load_mtl(&dir.join(name), &mut mtl_materials)?;
+                }
+            },
+            "usemtl" => {
+                let name = Some(args.join(" "));
+                current_mesh = match meshes.iter().position(|(n, _)| *n == name) {
+                    Some(i) => i,
+                    None => {
+                        meshes.push((name, MeshBuilder::default()));
+                        meshes.len() - 1
+                    },
+                };
+            },
+            "f" => {
+                if args.len() < 3 {
+                    return Err(cursor.error("face needs at least 3 vertices"));
+                }
+                let mesh = &mut meshes[current_mesh].1;
+                let mut face: Vec<u32> = Vec::with_capacity(args.len());
+                for vertex in &args {
+                    // Vertices are v, v/vt, v//vn or v/vt/vn
+                    let mut parts = vertex.split('/');
+                    let vi = resolve_index(&cursor, parts.next().unwrap_or(""), positions.len())?;
+                    let ti = match parts.next() {
+                        Some(s) if !s.is_empty() => Some(resolve_index(&cursor, s, uvs.len())?),
+                        _ => None,
+                    };
+                    let ni = match parts.next() {
+                        Some(s) if !s.is_empty() => Some(resolve_index(&cursor, s, normals.len())?),
+                        _ => None,
+                    };
+                    mesh.missing_uvs |= ti.is_none();
+                    mesh.missing_normals |= ni.is_none();
+
+                    let next_index = mesh.positions.len() as u32;
+                    let index = *mesh.vertex_map.entry((vi, ti, ni)).or_insert(next_index);
+                    if index == next_index {
+                        mesh.positions.push(positions[vi]);
+                        mesh.uvs.push(ti.map_or(Vec2::ZERO, |i| uvs[i]));
+                        mesh.normals.push(ni.map_or(Vec3::ZERO, |i| normals[i]));
+                    }
+                    face.push(index);
+                }
+                // Triangulate polygons as a fan
+                for i in 1..face.len() - 1 {
+                    mesh.indices.push([face[0], face[i], face[i + 1]]);
+                }
+            },
+            // Objects, groups and smoothing groups don't affect the result
+            _ => (),
+        }
+    }
+
+    let objects = meshes.into_iter()
+        .filter(|(_, mesh)| !mesh.indices.is_empty())
+        .map(|(name, mut mesh)| {
+            let material = name.as_ref()
+                .and_then(|name| mtl_materials.get(name))
+                .map_or(default_material, |mtl| mtl.to_material());
+            // Meshes have either all or no normals and UVs
+            if mesh.missing_normals {
+                mesh.normals.clear();
+            }
+            if mesh.missing_uvs {
+                mesh.uvs.clear();
+            }
+            Object::TriangleMesh(TriangleMesh::new(mesh.positions, mesh.normals, mesh.uvs, mesh.indices, material))
+        })
+        .collect();
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write files into a directory for the test, returning the path of the first
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obj-test-{}-{}", std::process::id(), test));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        dir.join(files[0].0)
+    }
+
+    fn default_material() -> Material {
+        Material::Lambertian(Lambertian { albedo: Texture::Solid(Color::ONE) })
+    }
+
+    fn load_meshes(test: &str, files: &[(&str, &str)]) -> Vec<TriangleMesh> {
+        load_obj(&write_files(test, files), default_material()).unwrap().into_iter()
+            .map(|object| match object {
+                Object::TriangleMesh(mesh) => mesh,
+                _ => panic!("expected only meshes"),
+            })
+            .collect()
+    }
+
+    fn parse_error_line(result: Result<Vec<Object>, ObjError>) -> (PathBuf, usize) {
+        match result {
+            Err(ObjError::Parse { path, line, .. }) => (path, line),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 2\n";
+
+    #[test]
+    fn face_formats() {
+        let cases = [
+            ("positions", "f 1 2 3", false, false),
+            ("uvs", "f 1/1 2/2 3/3", true, false),
+            ("normals", "f 1//1 2//1 3//1", false, true),
+            ("uvs-normals", "f 1/1/1 2/2/1 3/3/1", true, true),
+        ];
+        for &(test, face, has_uvs, has_normals) in cases.iter() {
+            let meshes = load_meshes(test, &[("mesh.obj", &format!("{}{}\n", TRIANGLE, face))]);
+            assert_eq!(meshes.len(), 1);
+            let mesh = &meshes[0];
+            assert_eq!(mesh.positions, vec![Point3::ZERO, Point3::X, Point3::Y]);
+            assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+            assert_eq!(mesh.uvs, if has_uvs { vec![Vec2::ZERO, Vec2::X, Vec2::Y] } else { vec![] }, "{}", test);
+            // Normals are normalized
+            assert_eq!(mesh.normals, if has_normals { vec![Vec3::Z; 3] } else { vec![] }, "{}", test);
+        }
+    }
+
+    #[test]
+    fn mixed_faces_drop_partial_attributes() {
+        let obj = format!("{}v 1 1 0\nf 1/1/1 2/2/1 3/3/1\nf 2 4 3\n", TRIANGLE);
+        let meshes = load_meshes("mixed", &[("mesh.obj", &obj)]);
+        assert_eq!(meshes[0].indices.len(), 2);
+        assert!(meshes[0].normals.is_empty());
+        assert!(meshes[0].uvs.is_empty());
+    }
+
+    #[test]
+    fn zero_normals_are_not_nan() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 0\nf 1//1 2//1 3//1\n";
+        let meshes = load_meshes("zero-normal", &[("mesh.obj", obj)]);
+        assert_eq!(meshes[0].normals, vec![Vec3::ZERO; 3]);
+    }
+
+    #[test]
+    fn negative_indices() {
+        let meshes = load_meshes("negative", &[("mesh.obj", &format!("{}f -3/-3/-1 -2/-2/-1 -1/-1/-1\n", TRIANGLE))]);
+        assert_eq!(meshes[0].positions, vec![Point3::ZERO, Point3::X, Point3::Y]);
+        assert_eq!(meshes[0].uvs, vec![Vec2::ZERO, Vec2::X, Vec2::Y]);
+        assert_eq!(meshes[0].indices, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn fan_triangulation() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0.5 2 0\nv 0 1 0\nf 1 2 3 4 5\n";
+        let meshes = load_meshes("fan", &[("mesh.obj", obj)]);
+        assert_eq!(meshes[0].indices, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn usemtl_groups() {
+        let obj = "mtllib mesh.mtl\n\
+            v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+            f 1 2 3\n\
+            usemtl red\nf 1 2 3\n\
+            usemtl light\nf 3 2 1\n\
+            usemtl red\nf 2 3 1\n\
+            usemtl unused\n";
+        let mtl = "newmtl red\nKd 1 0 0\nnewmtl light\nKe 4 4 4\n";
+        let meshes = load_meshes("usemtl", &[("mesh.obj", obj), ("mesh.mtl", mtl)]);
+        // Faces before any usemtl, then each material in order of first use, without empty groups
+        assert_eq!(meshes.len(), 3);
+        assert_eq!(meshes[0].indices.len(), 1);
+        assert!(matches!(meshes[0].material, Material::Lambertian(Lambertian { albedo: Texture::Solid(c) }) if c == Color::ONE));
+        assert_eq!(meshes[1].indices.len(), 2);
+        assert!(matches!(meshes[1].material, Material::Lambertian(Lambertian { albedo: Texture::Solid(c) }) if c == Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(meshes[2].indices.len(), 1);
+        assert!(matches!(meshes[2].material, Material::DiffuseLight(DiffuseLight { emit }) if emit == Color::splat(4.0)));
+    }
+
+    #[test]
+    fn mtl_mapping() {
+        let mtl = "newmtl light\nKe 1 2 3\n\
+            newmtl glass\nd 0.5\nNi 1.33\n\
+            newmtl glass_illum\nillum 7\n\
+            newmtl rough_dielectric\nKd 0.5 0.5 0.5\nPr 0.3\n\
+            newmtl metal_pbr\nPm 1\n\
+            newmtl shiny\nKd 0.1 0.1 0.1\nKs 0.9 0.9 0.9\nNs 1000\n\
+            newmtl matte\nKd 0.2 0.3 0.4\n";
+        let mut materials = HashMap::new();
+        load_mtl(&write_files("mtl", &[("test.mtl", mtl)]), &mut materials).unwrap();
+        let material = |name: &str| materials[name].to_material();
+
+        assert!(matches!(material("light"), Material::DiffuseLight(DiffuseLight { emit }) if emit == Color::new(1.0, 2.0, 3.0)));
+        assert!(matches!(material("glass"), Material::Dielectric(Dielectric { ir }) if ir == 1.33));
+        assert!(matches!(material("glass_illum"), Material::Dielectric(Dielectric { ir }) if ir == 1.5));
+        // Pr without Pm is a rough dielectric, not a metal
+        assert!(matches!(material("rough_dielectric"), Material::MetallicRoughness(m) if m.metallic == 0.0 && m.roughness == 0.3));
+        assert!(matches!(material("metal_pbr"), Material::MetallicRoughness(m) if m.metallic == 1.0 && m.roughness == 1.0));
+        assert!(matches!(material("shiny"), Material::Metal(Metal { fuzz, .. }) if fuzz < 0.1));
+        assert!(matches!(material("matte"), Material::Lambertian(Lambertian { albedo: Texture::Solid(c) }) if c == Color::new(0.2, 0.3, 0.4)));
+    }
+
+    #[test]
+    fn parse_error_lines() {
+        let path = write_files("bad-number", &[("mesh.obj", "v 0 0 0\n\n# comment\nv 1 x 0\n")]);
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), (path, 4));
+
+        let path = write_files("bad-index", &[("mesh.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n")]);
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), (path, 4));
+
+        let path = write_files("short-face", &[("mesh.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n")]);
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), (path, 3));
+
+        // Errors in an MTL file name it and its line
+        let path = write_files("bad-mtl", &[("mesh.obj", "\nmtllib bad.mtl\n"), ("bad.mtl", "# comment\nKd 1 1 1\n")]);
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), (path.with_file_name("bad.mtl"), 2));
+    }
+}
@@ -1,19 +1,25 @@
+use bvh::bvh::BVH;
+use glam::Affine3A;
+use rand::Rng;
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
 
 use crate::material::Material;
-use crate::shared::{AABB, BHShape, Bounded, Point3, Ray, RayQuery, Vec3};
+use crate::shared::{AABB, BHShape, Bounded, Point3, Ray, RayQuery, SampleRng, TRACE_EPSILON, Vec2, Vec3, orthonormal_basis, sample_unit_disk};
 
 /// Information of a ray hit
 pub struct HitRecord {
     pub point: Point3,
     pub normal: Vec3,
     pub t: f32,
+    pub uv: Vec2,
     pub front_face: bool,
     pub material: Material,
+    // Index of the hit object in the scene
+    pub object: usize,
 }
 
 impl HitRecord {
-    pub fn new(ray: Ray, t: f32, outward_normal: Vec3, material: Material) -> Self {
+    pub fn new(ray: Ray, t: f32, outward_normal: Vec3, uv: Vec2, material: Material) -> Self {
         let front_face = ray.direction.dot(outward_normal) < 0.0;
         let normal = if front_face {
             outward_normal
@@ -24,8 +30,24 @@ impl HitRecord {
             point: ray.at(t),
             normal: normal,
             t: t,
+            uv: uv,
             front_face: front_face,
             material: material,
+            object: 0,
+        }
+    }
+
+    /// Scattering event at t along a ray inside a participating medium, which has no surface so
+    /// the normal just faces back along the ray
+    pub fn in_medium(ray: Ray, t: f32, material: Material) -> Self {
+        HitRecord {
+            point: ray.at(t),
+            normal: -ray.direction.normalize(),
+            t: t,
+            uv: Vec2::ZERO,
+            front_face: true,
+            material: material,
+            object: 0,
         }
     }
 }
@@ -57,12 +79,158 @@ impl BHShape for HittableBounds {
 
 /// An object in the scene which can be hit with a ray
 pub trait RayHittable: Serialize + DeserializeOwned + Send + Sync {
-    // Intersect ray with object
-    fn intersect(&self, query: RayQuery) -> Option<HitRecord>;
+    // Intersect ray with object, where prototypes is the scene's table of objects for instances and
+    // rng is for objects which are hit at random such as media
+    fn intersect(&self, query: RayQuery, prototypes: &[Object], rng: &mut SampleRng) -> Option<HitRecord>;
     // Return bounds
     fn compute_bounds(&self, index: usize) -> HittableBounds;
 }
 
+/// Any kind of object which can be placed in a scene. This is an enum rather than a trait object
+/// so the scene can be serialized and sent to workers.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub enum Object {
+    Sphere(Sphere),
+    Triangle(Triangle),
+    TriangleMesh(TriangleMesh),
+    ConstantMedium(ConstantMedium),
+    Moving(Moving),
+    Instance(Instance),
+    Plane(Plane),
+    Quad(Quad),
+    Disk(Disk),
+    AxisAlignedBox(AxisAlignedBox),
+    Cylinder(Cylinder),
+}
+
+impl RayHittable for Object {
+    fn intersect(&self, query: RayQuery, prototypes: &[Object], rng: &mut SampleRng) -> Option<HitRecord> {
+        match self {
+            Object::Sphere(o) => o.intersect(query, prototypes, rng),
+            Object::Triangle(o) => o.intersect(query, prototypes, rng),
+            Object::TriangleMesh(o) => o.intersect(query, prototypes, rng),
+            Object::ConstantMedium(o) => o.intersect(query, prototypes, rng),
+            Object::Moving(o) => o.intersect(query, prototypes, rng),
+            Object::Instance(o) => o.intersect(query, prototypes, rng),
+            Object::Plane(o) => o.intersect(query, prototypes, rng),
+            Object::Quad(o) => o.intersect(query, prototypes, rng),
+            Object::Disk(o) => o.intersect(query, prototypes, rng),
+            Object::AxisAlignedBox(o) => o.intersect(query, prototypes, rng),
+            Object::Cylinder(o) => o.intersect(query, prototypes, rng),
+        }
+    }
+
+    fn compute_bounds(&self, index: usize) -> HittableBounds {
+        match self {
+            Object::Sphere(o) => o.compute_bounds(index),
+            Object::Triangle(o) => o.compute_bounds(index),
+            Object::TriangleMesh(o) => o.compute_bounds(index),
+            Object::ConstantMedium(o) => o.compute_bounds(index),
+            Object::Moving(o) => o.compute_bounds(index),
+            Object::Instance(o) => o.compute_bounds(index),
+            Object::Plane(o) => o.compute_bounds(index),
+            Object::Quad(o) => o.compute_bounds(index),
+            Object::Disk(o) => o.compute_bounds(index),
+            Object::AxisAlignedBox(o) => o.compute_bounds(index),
+            Object::Cylinder(o) => o.compute_bounds(index),
+        }
+    }
+}
+
+impl Object {
+    /// Whether the object has finite bounds. Unbounded objects are kept out of the scene BVH, as a
+    /// box around them would cover everything else.
+    pub fn is_bounded(&self) -> bool {
+        match self {
+            Object::Plane(_) => false,
+            Object::ConstantMedium(o) => o.boundary.is_bounded(),
+            Object::Moving(o) => o.object.is_bounded(),
+            Object::Instance(o) => o.bounded,
+            _ => true,
+        }
+    }
+
+    /// Whether the object emits light and can be sampled directly. Instances can't be, as that
+    /// would need their prototype, and neither can planes, boxes or cylinders, so they're only
+    /// found by scattered rays.
+    pub fn is_light(&self) -> bool {
+        let emits = |material: &Material| matches!(material, Material::DiffuseLight(_));
+        match self {
+            Object::Sphere(o) => emits(&o.material),
+            // Shapes without area have no points to sample
+            Object::Triangle(o) => emits(&o.material) && o.area() > 0.0,
+            Object::TriangleMesh(o) => emits(&o.material) && o.area() > 0.0,
+            Object::ConstantMedium(_) => false,
+            Object::Moving(o) => o.object.is_light(),
+            Object::Instance(_) => false,
+            Object::Quad(o) => emits(&o.material) && o.area() > 0.0,
+            Object::Disk(o) => emits(&o.material) && o.area() > 0.0,
+            Object::Plane(_) | Object::AxisAlignedBox(_) | Object::Cylinder(_) => false,
+        }
+    }
+
+    /// Sample a direction from origin towards a point on the object at time, for sampling it as a
+    /// light
+    pub fn sample_direction<T: Rng>(&self, origin: Point3, time: f32, rng: &mut T) -> Vec3 {
+        match self {
+            Object::Sphere(o) => o.sample_direction(origin, rng),
+            Object::Triangle(o) => {
+                let [p0, p1, p2] = o.vertices;
+                sample_triangle(p0, p1, p2, rng.gen(), rng.gen()) - origin
+            },
+            Object::TriangleMesh(o) => o.sample_point(rng) - origin,
+            Object::ConstantMedium(o) => o.boundary.sample_direction(origin, time, rng),
+            Object::Moving(o) => o.object.sample_direction(origin - o.offset(time), time, rng),
+            Object::Quad(o) => o.sample_point(rng) - origin,
+            Object::Disk(o) => o.sample_point(rng) - origin,
+            Object::Instance(_) | Object::Plane(_) | Object::AxisAlignedBox(_) | Object::Cylinder(_) => {
+                unreachable!("only objects which are lights are sampled")
+            },
+        }
+    }
+
+    /// Solid angle density with which sample_direction from origin would produce the hit at time
+    pub fn pdf_value(&self, origin: Point3, time: f32, hit: &HitRecord) -> f32 {
+        match self {
+            Object::Sphere(o) => o.pdf_value(origin),
+            Object::Triangle(o) => area_to_solid_angle(origin, hit, 1.0 / o.area()),
+            Object::TriangleMesh(o) => area_to_solid_angle(origin, hit, 1.0 / o.area()),
+            Object::ConstantMedium(o) => o.boundary.pdf_value(origin, time, hit),
+            Object::Moving(o) => {
+                // Relative to the object where it was at that time
+                let offset = o.offset(time);
+                let hit = HitRecord { point: hit.point - offset, ..*hit };
+                o.object.pdf_value(origin - offset, time, &hit)
+            },
+            Object::Quad(o) => area_to_solid_angle(origin, hit, 1.0 / o.area()),
+            Object::Disk(o) => area_to_solid_angle(origin, hit, 1.0 / o.area()),
+            Object::Instance(_) | Object::Plane(_) | Object::AxisAlignedBox(_) | Object::Cylinder(_) => 0.0,
+        }
+    }
+}
+
+/// Convert a density with respect to area at the hit to one with respect to solid angle at origin
+fn area_to_solid_angle(origin: Point3, hit: &HitRecord, area_pdf: f32) -> f32 {
+    let to_hit = hit.point - origin;
+    let distance_squared = to_hit.length_squared();
+    let cosine = (to_hit.dot(hit.normal) / distance_squared.sqrt()).abs();
+    if cosine < 1e-6 {
+        return 0.0;
+    }
+    area_pdf * distance_squared / cosine
+}
+
+fn triangle_area(p0: Point3, p1: Point3, p2: Point3) -> f32 {
+    0.5 * (p1 - p0).cross(p2 - p0).length()
+}
+
+/// Map two uniform numbers to a uniformly distributed point on a triangle
+fn sample_triangle(p0: Point3, p1: Point3, p2: Point3, u1: f32, u2: f32) -> Point3 {
+    let su1 = u1.sqrt();
+    (1.0 - su1) * p0 + su1 * (1.0 - u2) * p1 + su1 * u2 * p2
+}
+
 #[derive(Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Sphere {
@@ -85,8 +253,41 @@ impl Sphere {
     }
 }
 
+impl Sphere {
+    /// Cosine of the half angle of the cone the sphere subtends from origin, None if inside
+    fn cos_theta_max(&self, origin: Point3) -> Option<f32> {
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius_sq {
+            return None;
+        }
+        Some(f32::sqrt(1.0 - self.radius_sq / distance_squared))
+    }
+
+    /// Uniformly sample the cone of directions from origin which hit the sphere
+    fn sample_direction<T: Rng>(&self, origin: Point3, rng: &mut T) -> Vec3 {
+        let to_center = self.center - origin;
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(c) => c,
+            None => return crate::shared::random_unit_vector(rng),
+        };
+        let cos_theta = 1.0 + rng.gen::<f32>() * (cos_theta_max - 1.0);
+        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+        let phi = 2.0 * std::f32::consts::PI * rng.gen::<f32>();
+        let (u, v, w) = orthonormal_basis(to_center.normalize());
+        u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta
+    }
+
+    fn pdf_value(&self, origin: Point3) -> f32 {
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => 1.0 / (2.0 * std::f32::consts::PI * (1.0 - cos_theta_max)),
+            // Inside the sphere directions are sampled uniformly
+            None => 1.0 / (4.0 * std::f32::consts::PI),
+        }
+    }
+}
+
 impl RayHittable for Sphere {
-    fn intersect(&self, query: RayQuery) -> Option<HitRecord> {
+    fn intersect(&self, query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
         let r = query.ray;
         let oc = r.origin - self.center;
         let a = r.direction.length_squared();
@@ -110,7 +311,8 @@ impl RayHittable for Sphere {
         let t = root;
         let point = r.at(t);
         let outward_normal = (point - self.center) * self.radius_rcp;
-        let record = HitRecord::new(r, t, outward_normal, self.material.clone());
+        let uv = sphere_uv(outward_normal);
+        let record = HitRecord::new(r, t, outward_normal, uv, self.material.clone());
 
         return Some(record);
     }
@@ -129,3 +331,995 @@ impl RayHittable for Sphere {
         }
     }
 }
+
+/// UV of a point on the unit sphere, with u around the Y axis and v from bottom to top
+fn sphere_uv(p: Vec3) -> Vec2 {
+    let theta = f32::acos(-p.y);
+    let phi = f32::atan2(-p.z, p.x) + std::f32::consts::PI;
+    Vec2::new(phi / (2.0 * std::f32::consts::PI), theta / std::f32::consts::PI)
+}
+
+/// Moller-Trumbore ray/triangle intersection, returning t and the barycentrics of p1 and p2
+fn intersect_triangle(query: &RayQuery, p0: Point3, p1: Point3, p2: Point3) -> Option<(f32, f32, f32)> {
+    let r = query.ray;
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = r.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+    if det.abs() < 1e-12 {
+        return None; // Ray is parallel to the triangle
+    }
+    let det_rcp = 1.0 / det;
+
+    let tvec = r.origin - p0;
+    let b1 = tvec.dot(pvec) * det_rcp;
+    if b1 < 0.0 || b1 > 1.0 {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let b2 = r.direction.dot(qvec) * det_rcp;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * det_rcp;
+    if t < query.t_min || query.t_max < t {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// Build a hit record for a triangle given its barycentrics, using the geometric normal for
+/// facing and the interpolated vertex normal for shading
+fn triangle_hit_record(
+    ray: Ray,
+    t: f32,
+    b1: f32,
+    b2: f32,
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [Vec2; 3],
+    material: Material,
+) -> HitRecord {
+    let b0 = 1.0 - b1 - b2;
+    let geometric_normal = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]).normalize();
+    let uv = b0 * uvs[0] + b1 * uvs[1] + b2 * uvs[2];
+    let mut record = HitRecord::new(ray, t, geometric_normal, uv, material);
+    if let Some(n) = normals {
+        // Zero vertex normals leave the geometric normal
+        let shading_normal = (b0 * n[0] + b1 * n[1] + b2 * n[2]).normalize_or_zero();
+        if shading_normal != Vec3::ZERO {
+            record.normal = if record.front_face { shading_normal } else { -shading_normal };
+        }
+    }
+    record
+}
+
+/// Bounds of a set of points, padded so that axis aligned triangles don't produce flat boxes
+fn points_aabb(points: impl Iterator<Item = Point3>) -> AABB {
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for p in points {
+        min = min.min(p);
+        max = max.max(p);
+    }
+    let padding = Vec3::splat(1e-4);
+    AABB::with_bounds(min - padding, max + padding)
+}
+
+const DEFAULT_TRIANGLE_UVS: [Vec2; 3] = [Vec2::ZERO, Vec2::X, Vec2::Y];
+
+/// A single triangle with optional per-vertex normals and UVs
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub uvs: [Vec2; 3],
+    pub material: Material,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Point3; 3], material: Material) -> Self {
+        Triangle {
+            vertices: vertices,
+            normals: None,
+            uvs: DEFAULT_TRIANGLE_UVS,
+            material: material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [Vec2; 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    fn area(&self) -> f32 {
+        let [p0, p1, p2] = self.vertices;
+        triangle_area(p0, p1, p2)
+    }
+}
+
+impl RayHittable for Triangle {
+    fn intersect(&self, query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
+        let [p0, p1, p2] = self.vertices;
+        let (t, b1, b2) = intersect_triangle(&query, p0, p1, p2)?;
+        Some(triangle_hit_record(query.ray, t, b1, b2, self.vertices, self.normals, self.uvs, self.material))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        HittableBounds {
+            aabb: points_aabb(self.vertices.iter().copied()),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+/// An indexed triangle mesh sharing vertex buffers between its triangles. The mesh holds its own
+/// BVH over the triangles, so it is a single object in the scene BVH.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    // Per-vertex normals, either empty or the same length as positions
+    pub normals: Vec<Vec3>,
+    // Per-vertex UVs, either empty or the same length as positions
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Material,
+
+    // Bounds of each triangle
+    bounds: Vec<HittableBounds>,
+
+    // Cumulative triangle areas, for sampling points on the mesh
+    area_cdf: Vec<f32>,
+
+    // Acceleration structure over the triangles
+    bvh: Option<BVH>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        indices: Vec<[u32; 3]>,
+        material: Material,
+    ) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len(), "mesh must have a normal per vertex");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(), "mesh must have a UV per vertex");
+        assert!(
+            indices.iter().flatten().all(|&i| (i as usize) < positions.len()),
+            "mesh index out of range",
+        );
+
+        let mut mesh = TriangleMesh {
+            positions: positions,
+            normals: normals,
+            uvs: uvs,
+            indices: indices,
+            material: material,
+            bounds: Vec::new(),
+            area_cdf: Vec::new(),
+            bvh: None,
+        };
+
+        // Build the BVH over the triangles. The bvh crate can't build over no shapes.
+        mesh.bounds = (0..mesh.indices.len()).map(|i| HittableBounds {
+            aabb: points_aabb(mesh.triangle_vertices(i).iter().copied()),
+            node_index: 0,
+            hittable_index: i,
+        }).collect();
+        if !mesh.bounds.is_empty() {
+            mesh.bvh = Some(BVH::build(&mut mesh.bounds));
+        }
+
+        let mut total_area = 0.0;
+        mesh.area_cdf = (0..mesh.indices.len()).map(|i| {
+            let [p0, p1, p2] = mesh.triangle_vertices(i);
+            total_area += triangle_area(p0, p1, p2);
+            total_area
+        }).collect();
+
+        mesh
+    }
+
+    fn triangle_vertices(&self, triangle_index: usize) -> [Point3; 3] {
+        let [i0, i1, i2] = self.indices[triangle_index];
+        [self.positions[i0 as usize], self.positions[i1 as usize], self.positions[i2 as usize]]
+    }
+
+    fn area(&self) -> f32 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// Uniformly sample a point on the surface of the mesh
+    fn sample_point<T: Rng>(&self, rng: &mut T) -> Point3 {
+        let target = rng.gen::<f32>() * self.area();
+        let triangle_index = self.area_cdf.partition_point(|&a| a < target).min(self.indices.len() - 1);
+        let [p0, p1, p2] = self.triangle_vertices(triangle_index);
+        sample_triangle(p0, p1, p2, rng.gen(), rng.gen())
+    }
+
+    fn triangle_hit_record(&self, ray: Ray, t: f32, b1: f32, b2: f32, triangle_index: usize) -> HitRecord {
+        let [i0, i1, i2] = self.indices[triangle_index];
+        let normals = if self.normals.is_empty() {
+            None
+        } else {
+            Some([self.normals[i0 as usize], self.normals[i1 as usize], self.normals[i2 as usize]])
+        };
+        let uvs = if self.uvs.is_empty() {
+            DEFAULT_TRIANGLE_UVS
+        } else {
+            [self.uvs[i0 as usize], self.uvs[i1 as usize], self.uvs[i2 as usize]]
+        };
+        let vertices = self.triangle_vertices(triangle_index);
+        triangle_hit_record(ray, t, b1, b2, vertices, normals, uvs, self.material)
+    }
+}
+
+impl RayHittable for TriangleMesh {
+    fn intersect(&self, mut query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
+        let bvh = self.bvh.as_ref()?;
+        let bvh_ray = query.ray.to_bvh();
+
+        // Find the closest triangle, shortening the ray as we go
+        let mut closest: Option<(f32, f32, f32, usize)> = None;
+        for bounds in bvh.traverse_iterator(&bvh_ray, &self.bounds) {
+            let [p0, p1, p2] = self.triangle_vertices(bounds.hittable_index);
+            if let Some((t, b1, b2)) = intersect_triangle(&query, p0, p1, p2) {
+                query.t_max = t;
+                closest = Some((t, b1, b2, bounds.hittable_index));
+            }
+        }
+
+        let (t, b1, b2, triangle_index) = closest?;
+        Some(self.triangle_hit_record(query.ray, t, b1, b2, triangle_index))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        HittableBounds {
+            aabb: points_aabb(self.positions.iter().copied()),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+/// Participating medium of constant density, such as smoke, filling a boundary object. Rays are
+/// scattered at random distances inside it, more often the denser it is. Only the first stretch of
+/// a ray inside the boundary is considered, so the boundary should be closed and convex.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct ConstantMedium {
+    pub boundary: Box<Object>,
+    // Chance of scattering per unit distance
+    pub density: f32,
+    // Material::Medium giving the albedo and phase function
+    pub material: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Object, density: f32, material: Material) -> Self {
+        ConstantMedium {
+            boundary: Box::new(boundary),
+            density: density,
+            material: material,
+        }
+    }
+}
+
+impl RayHittable for ConstantMedium {
+    fn intersect(&self, query: RayQuery, prototypes: &[Object], rng: &mut SampleRng) -> Option<HitRecord> {
+        // Find where the line of the ray enters and leaves the boundary, then clip that to the query
+        let whole_line = RayQuery { ray: query.ray, t_min: f32::MIN, t_max: f32::MAX };
+        let entry = self.boundary.intersect(whole_line, prototypes, rng)?;
+        let exit = self.boundary.intersect(RayQuery { t_min: entry.t + TRACE_EPSILON, ..whole_line }, prototypes, rng)?;
+        let t_enter = entry.t.max(query.t_min);
+        let t_exit = exit.t.min(query.t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        // Distance to the scattering event is exponentially distributed
+        let distance = -(1.0 - rng.gen::<f32>()).ln() / self.density;
+        let t = t_enter + distance / query.ray.direction.length();
+        if t >= t_exit {
+            return None;
+        }
+        Some(HitRecord::in_medium(query.ray, t, self.material))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        self.boundary.compute_bounds(hittable_index)
+    }
+}
+
+/// An object moving in a straight line while the shutter is open, from its own position at time0
+/// to that plus translation at time1. It stays put before time0 and after time1.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Moving {
+    pub object: Box<Object>,
+    pub translation: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+}
+
+impl Moving {
+    pub fn new(object: Object, translation: Vec3, time0: f32, time1: f32) -> Self {
+        Moving {
+            object: Box::new(object),
+            translation: translation,
+            time0: time0,
+            time1: time1,
+        }
+    }
+
+    /// How far the object has moved at time
+    fn offset(&self, time: f32) -> Vec3 {
+        if self.time1 <= self.time0 {
+            return if time < self.time0 { Vec3::ZERO } else { self.translation };
+        }
+        let f = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        f * self.translation
+    }
+}
+
+impl RayHittable for Moving {
+    fn intersect(&self, query: RayQuery, prototypes: &[Object], rng: &mut SampleRng) -> Option<HitRecord> {
+        // Move the ray instead of the object
+        let offset = self.offset(query.ray.time);
+        let mut moved = query;
+        moved.ray.origin -= offset;
+        let mut hit = self.object.intersect(moved, prototypes, rng)?;
+        hit.point += offset;
+        Some(hit)
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        // The motion is a straight line, so the boxes at either end bound everywhere between
+        let start = self.object.compute_bounds(hittable_index);
+        let end = AABB::with_bounds(start.aabb.min + self.translation, start.aabb.max + self.translation);
+        HittableBounds {
+            aabb: start.aabb.join(&end),
+            ..start
+        }
+    }
+}
+
+/// A transformed copy of one of the scene's prototype objects, so geometry placed many times is
+/// only stored once
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Instance {
+    // Index into the scene's prototypes
+    pub prototype: usize,
+    // From the prototype's space to world space
+    pub transform: Affine3A,
+    inverse: Affine3A,
+    // Bounds of the prototype in its own space, so the instance can be bounded without it
+    prototype_bounds: AABB,
+    // Whether the prototype is bounded, as instances of planes aren't
+    bounded: bool,
+}
+
+impl Instance {
+    pub fn new(prototype: usize, prototype_object: &Object, transform: Affine3A) -> Self {
+        Instance {
+            prototype: prototype,
+            transform: transform,
+            inverse: transform.inverse(),
+            prototype_bounds: prototype_object.compute_bounds(0).aabb,
+            bounded: prototype_object.is_bounded(),
+        }
+    }
+}
+
+impl RayHittable for Instance {
+    fn intersect(&self, query: RayQuery, prototypes: &[Object], rng: &mut SampleRng) -> Option<HitRecord> {
+        // The direction isn't normalized, so t is the same in both spaces
+        let mut local = query;
+        local.ray.origin = self.inverse.transform_point3(query.ray.origin);
+        local.ray.direction = self.inverse.transform_vector3(query.ray.direction);
+        let mut hit = prototypes[self.prototype].intersect(local, prototypes, rng)?;
+
+        hit.point = query.ray.at(hit.t);
+        // Normals transform by the inverse transpose, which keeps them facing the same side
+        hit.normal = self.inverse.matrix3.transpose().mul_vec3(hit.normal).normalize();
+        Some(hit)
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        // Bound the transformed corners of the prototype's box
+        let (min, max) = (self.prototype_bounds.min, self.prototype_bounds.max);
+        let corners = (0..8).map(|i| {
+            let corner = Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            self.transform.transform_point3(corner)
+        });
+        HittableBounds {
+            aabb: points_aabb(corners),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+/// Distance along the ray to where it crosses the plane through point with the given normal, if
+/// that's within the query
+fn intersect_plane(query: &RayQuery, point: Point3, normal: Vec3) -> Option<f32> {
+    let denom = normal.dot(query.ray.direction);
+    if denom.abs() < 1e-12 {
+        return None; // Ray is parallel to the plane
+    }
+    let t = normal.dot(point - query.ray.origin) / denom;
+    if t < query.t_min || query.t_max < t {
+        return None;
+    }
+    Some(t)
+}
+
+/// Half size along each axis of the bounds of a disk with the given unit normal and radius
+fn disk_extent(normal: Vec3, radius: f32) -> Vec3 {
+    let extent = |n: f32| radius * f32::sqrt((1.0 - n * n).max(0.0));
+    Vec3::new(extent(normal.x), extent(normal.y), extent(normal.z))
+}
+
+/// How far planes reach in their bounds. Planes are kept out of the scene BVH, but are still given
+/// finite bounds for instances and media made of them, which the bvh crate needs.
+const PLANE_EXTENT: f32 = 1e6;
+
+/// An infinite plane through point, with UVs in world units along the plane
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Vec3,
+    pub material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, material: Material) -> Self {
+        Plane {
+            point: point,
+            normal: normal.normalize(),
+            material: material,
+        }
+    }
+}
+
+impl RayHittable for Plane {
+    fn intersect(&self, query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
+        let t = intersect_plane(&query, self.point, self.normal)?;
+        let (u, v, _) = orthonormal_basis(self.normal);
+        let offset = query.ray.at(t) - self.point;
+        let uv = Vec2::new(offset.dot(u), offset.dot(v));
+        Some(HitRecord::new(query.ray, t, self.normal, uv, self.material))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        let extent = disk_extent(self.normal, PLANE_EXTENT);
+        HittableBounds {
+            aabb: points_aabb([self.point - extent, self.point + extent].iter().copied()),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+/// A parallelogram with a corner at corner and edges u and v, facing along u x v. UVs go from 0 to
+/// 1 along the edges.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Quad {
+    pub corner: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Material,
+    normal: Vec3,
+    // u x v over its squared length, for finding the coordinates of hits along the edges
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(corner: Point3, u: Vec3, v: Vec3, material: Material) -> Self {
+        let n = u.cross(v);
+        Quad {
+            corner: corner,
+            u: u,
+            v: v,
+            material: material,
+            normal: n.normalize(),
+            w: n / n.length_squared(),
+        }
+    }
+
+    fn area(&self) -> f32 {
+        self.u.cross(self.v).length()
+    }
+
+    /// Uniformly sample a point on the quad
+    fn sample_point<T: Rng>(&self, rng: &mut T) -> Point3 {
+        self.corner + rng.gen::<f32>() * self.u + rng.gen::<f32>() * self.v
+    }
+}
+
+impl RayHittable for Quad {
+    fn intersect(&self, query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
+        let t = intersect_plane(&query, self.corner, self.normal)?;
+        let planar = query.ray.at(t) - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if alpha < 0.0 || alpha > 1.0 || beta < 0.0 || beta > 1.0 {
+            return None;
+        }
+        Some(HitRecord::new(query.ray, t, self.normal, Vec2::new(alpha, beta), self.material))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        let corners = [self.corner, self.corner + self.u, self.corner + self.v, self.corner + self.u + self.v];
+        HittableBounds {
+            aabb: points_aabb(corners.iter().copied()),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+/// A flat disk, with u going around the normal and v from the center to the rim
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f32, material: Material) -> Self {
+        Disk {
+            center: center,
+            normal: normal.normalize(),
+            radius: radius,
+            material: material,
+        }
+    }
+
+    fn area(&self) -> f32 {
+        std::f32::consts::PI * self.radius * self.radius
+    }
+
+    /// Uniformly sample a point on the disk
+    fn sample_point<T: Rng>(&self, rng: &mut T) -> Point3 {
+        let (u, v, _) = orthonormal_basis(self.normal);
+        let p = self.radius * sample_unit_disk(Vec2::new(rng.gen(), rng.gen()));
+        self.center + p.x * u + p.y * v
+    }
+}
+
+impl RayHittable for Disk {
+    fn intersect(&self, query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
+        let t = intersect_plane(&query, self.center, self.normal)?;
+        let offset = query.ray.at(t) - self.center;
+        let distance_squared = offset.length_squared();
+        if distance_squared > self.radius * self.radius {
+            return None;
+        }
+        let (u, v, _) = orthonormal_basis(self.normal);
+        let phi = f32::atan2(offset.dot(v), offset.dot(u)) + std::f32::consts::PI;
+        let uv = Vec2::new(phi / (2.0 * std::f32::consts::PI), distance_squared.sqrt() / self.radius);
+        Some(HitRecord::new(query.ray, t, self.normal, uv, self.material))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        let extent = disk_extent(self.normal, self.radius);
+        HittableBounds {
+            aabb: points_aabb([self.center - extent, self.center + extent].iter().copied()),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+/// A solid box between two corners, with UVs from 0 to 1 across each face
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct AxisAlignedBox {
+    pub min: Point3,
+    pub max: Point3,
+    pub material: Material,
+}
+
+impl AxisAlignedBox {
+    pub fn new(min: Point3, max: Point3, material: Material) -> Self {
+        AxisAlignedBox {
+            min: min,
+            max: max,
+            material: material,
+        }
+    }
+}
+
+impl RayHittable for AxisAlignedBox {
+    fn intersect(&self, query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
+        // Slab test, taking the entry if it's in the query or else the exit. Axes the ray is
+        // parallel to are handled separately, as the origin can lie on a slab, giving 0 * inf.
+        let r = query.ray;
+        let (mut t_enter, mut enter_axis) = (f32::NEG_INFINITY, 0);
+        let (mut t_exit, mut exit_axis) = (f32::INFINITY, 0);
+        for axis in 0..3 {
+            if r.direction[axis] == 0.0 {
+                if r.origin[axis] < self.min[axis] || r.origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let direction_rcp = 1.0 / r.direction[axis];
+            let t0 = (self.min[axis] - r.origin[axis]) * direction_rcp;
+            let t1 = (self.max[axis] - r.origin[axis]) * direction_rcp;
+            if t0.min(t1) > t_enter {
+                t_enter = t0.min(t1);
+                enter_axis = axis;
+            }
+            if t0.max(t1) < t_exit {
+                t_exit = t0.max(t1);
+                exit_axis = axis;
+            }
+        }
+        if t_enter > t_exit {
+            return None;
+        }
+        // The face hit is on the axis of the slab entered or exited, facing against or along the ray
+        let (t, axis, sign) = if query.t_min <= t_enter && t_enter <= query.t_max {
+            (t_enter, enter_axis, -r.direction[enter_axis].signum())
+        } else if query.t_min <= t_exit && t_exit <= query.t_max {
+            (t_exit, exit_axis, r.direction[exit_axis].signum())
+        } else {
+            return None;
+        };
+        let (u_axis, v_axis) = match axis {
+            0 => (2, 1),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        let mut outward_normal = Vec3::ZERO;
+        outward_normal[axis] = sign;
+
+        // Position across the face, which is the middle for a box that's flat along that axis
+        let p = r.at(t);
+        let size = self.max - self.min;
+        let across = |a: usize| if size[a] > 0.0 { (p[a] - self.min[a]) / size[a] } else { 0.5 };
+        let uv = Vec2::new(across(u_axis), across(v_axis));
+        Some(HitRecord::new(r, t, outward_normal, uv, self.material))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        HittableBounds {
+            // Padded like triangles, as the box can be flat
+            aabb: points_aabb([self.min, self.max].iter().copied()),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+/// A capped cylinder standing on the Y axis from its base center, which can be placed at other
+/// angles with an instance. On the side u goes around the axis and v up it, and the caps are
+/// mapped from above.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Cylinder {
+    pub center: Point3,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Material,
+}
+
+impl Cylinder {
+    pub fn new(center: Point3, radius: f32, height: f32, material: Material) -> Self {
+        Cylinder {
+            center: center,
+            radius: radius,
+            height: height,
+            material: material,
+        }
+    }
+}
+
+impl RayHittable for Cylinder {
+    fn intersect(&self, mut query: RayQuery, _prototypes: &[Object], _rng: &mut SampleRng) -> Option<HitRecord> {
+        let r = query.ray;
+        let o = r.origin - self.center;
+        let d = r.direction;
+        // Closest hit so far as t and outward normal
+        let mut closest: Option<(f32, Vec3)> = None;
+
+        // Side, within the height
+        let a = d.x * d.x + d.z * d.z;
+        if a > 1e-12 {
+            let half_b = o.x * d.x + o.z * d.z;
+            let c = o.x * o.x + o.z * o.z - self.radius * self.radius;
+            let discriminant = half_b * half_b - a * c;
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                for &t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a].iter() {
+                    let y = o.y + t * d.y;
+                    if query.t_min <= t && t <= query.t_max && 0.0 <= y && y <= self.height {
+                        let p = o + t * d;
+                        query.t_max = t;
+                        closest = Some((t, Vec3::new(p.x, 0.0, p.z) / self.radius));
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Caps, within the radius
+        let local_query = RayQuery { ray: Ray { origin: o, ..r }, ..query };
+        for &(y, normal) in [(0.0, -Vec3::Y), (self.height, Vec3::Y)].iter() {
+            if let Some(t) = intersect_plane(&local_query, Vec3::new(0.0, y, 0.0), normal) {
+                let p = o + t * d;
+                if p.x * p.x + p.z * p.z <= self.radius * self.radius && closest.map_or(true, |(ct, _)| t < ct) {
+                    closest = Some((t, normal));
+                }
+            }
+        }
+
+        let (t, outward_normal) = closest?;
+        let p = o + t * d;
+        let uv = if outward_normal.y == 0.0 {
+            let phi = f32::atan2(-p.z, p.x) + std::f32::consts::PI;
+            Vec2::new(phi / (2.0 * std::f32::consts::PI), p.y / self.height)
+        } else {
+            0.5 * (Vec2::new(p.x, p.z) / self.radius + Vec2::ONE)
+        };
+        Some(HitRecord::new(r, t, outward_normal, uv, self.material))
+    }
+
+    fn compute_bounds(&self, hittable_index: usize) -> HittableBounds {
+        let min = self.center - Vec3::new(self.radius, 0.0, self.radius);
+        let max = self.center + Vec3::new(self.radius, self.height, self.radius);
+        HittableBounds {
+            aabb: AABB::with_bounds(min, max),
+            node_index: 0,
+            hittable_index: hittable_index,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::shared::{Color, TRACE_INFINITY, sample_rng};
+    use crate::texture::Texture;
+
+    fn material() -> Material {
+        Material::Lambertian(Lambertian { albedo: Texture::Solid(Color::ONE) })
+    }
+
+    fn intersect(object: &Object, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        intersect_in(object, &[], origin, direction)
+    }
+
+    fn intersect_in(object: &Object, prototypes: &[Object], origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let query = RayQuery {
+            ray: Ray::new(origin, direction, 0.0),
+            t_min: TRACE_EPSILON,
+            t_max: TRACE_INFINITY,
+        };
+        object.intersect(query, prototypes, &mut sample_rng(0, 0, 0, 0, 0))
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn triangle() {
+        let triangle = Object::Triangle(Triangle::new([Point3::ZERO, Point3::X, Point3::Y], material()));
+        let hit = intersect(&triangle, Point3::new(0.25, 0.5, 2.0), -Vec3::Z).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_near(hit.normal, Vec3::Z);
+        // The default UVs are the barycentric coordinates of the second and third vertices
+        assert_near(hit.uv.extend(0.0), Vec3::new(0.25, 0.5, 0.0));
+        let hit = intersect(&triangle, Point3::new(0.25, 0.5, -2.0), Vec3::Z).unwrap();
+        assert!(!hit.front_face);
+        assert_near(hit.normal, -Vec3::Z);
+        assert!(intersect(&triangle, Point3::new(0.75, 0.75, 2.0), -Vec3::Z).is_none());
+        assert!(intersect(&triangle, Point3::new(0.25, 0.25, 2.0), Vec3::X).is_none());
+    }
+
+    #[test]
+    fn triangle_shading_normals() {
+        let triangle = Triangle::new([Point3::ZERO, Point3::X, Point3::Y], material())
+            .with_normals([Vec3::X, Vec3::Z, Vec3::Z]);
+        let hit = intersect(&Object::Triangle(triangle), Point3::new(0.25, 0.25, 2.0), -Vec3::Z).unwrap();
+        assert_near(hit.normal, Vec3::new(0.5, 0.0, 0.5).normalize());
+        // Zero normals leave the geometric normal rather than NaN
+        let triangle = Triangle::new([Point3::ZERO, Point3::X, Point3::Y], material()).with_normals([Vec3::ZERO; 3]);
+        let hit = intersect(&Object::Triangle(triangle), Point3::new(0.25, 0.25, 2.0), -Vec3::Z).unwrap();
+        assert_near(hit.normal, Vec3::Z);
+    }
+
+    fn unit_square_mesh() -> TriangleMesh {
+        let positions = vec![Point3::ZERO, Point3::X, Point3::new(1.0, 1.0, 0.0), Point3::Y];
+        TriangleMesh::new(positions, vec![], vec![], vec![[0, 1, 2], [0, 2, 3]], material())
+    }
+
+    #[test]
+    fn triangle_mesh() {
+        let mesh = Object::TriangleMesh(unit_square_mesh());
+        // -Vec3::Z has negative zero components, which the BVH must still traverse
+        for &(x, y) in [(0.75, 0.25), (0.25, 0.75)].iter() {
+            let hit = intersect(&mesh, Point3::new(x, y, 1.0), -Vec3::Z).unwrap();
+            assert!((hit.t - 1.0).abs() < 1e-5);
+            assert_near(hit.normal, Vec3::Z);
+        }
+        assert!(intersect(&mesh, Point3::new(1.5, 0.5, 1.0), -Vec3::Z).is_none());
+
+        // Two stacked meshes' nearest triangle is hit
+        let mut positions = unit_square_mesh().positions;
+        positions.extend(positions.clone().into_iter().map(|p| p + Vec3::Z));
+        let stacked = TriangleMesh::new(positions, vec![], vec![], vec![[0, 1, 2], [4, 5, 6]], material());
+        let hit = intersect(&Object::TriangleMesh(stacked), Point3::new(0.75, 0.25, 3.0), -Vec3::Z).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn triangle_mesh_sampling() {
+        let mesh = unit_square_mesh();
+        assert!((mesh.area() - 1.0).abs() < 1e-5);
+        let mut rng = sample_rng(0, 0, 0, 0, 0);
+        for _ in 0..100 {
+            let p = mesh.sample_point(&mut rng);
+            assert!(p.z == 0.0 && (0.0..=1.0).contains(&p.x) && (0.0..=1.0).contains(&p.y), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn box_faces() {
+        let object = Object::AxisAlignedBox(AxisAlignedBox::new(Point3::splat(-1.0), Point3::splat(1.0), material()));
+        let hit = intersect(&object, Point3::new(0.5, 0.0, 5.0), -Vec3::Z).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_near(hit.normal, Vec3::Z);
+        assert_near(hit.uv.extend(0.0), Vec3::new(0.75, 0.5, 0.0));
+        // From inside, the exit is hit from the back
+        let hit = intersect(&object, Point3::ZERO, Vec3::X).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!(!hit.front_face);
+        assert_near(hit.normal, -Vec3::X);
+        assert!(intersect(&object, Point3::new(0.0, 2.0, 5.0), -Vec3::Z).is_none());
+    }
+
+    #[test]
+    fn box_degenerate_cases() {
+        // Flat along Y, so there's no half size to divide by
+        let flat = Object::AxisAlignedBox(AxisAlignedBox::new(Point3::new(-1.0, 0.0, -1.0), Point3::new(1.0, 0.0, 1.0), material()));
+        let hit = intersect(&flat, Point3::new(0.0, 3.0, 0.0), -Vec3::Y).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::Y);
+        assert!(hit.uv.is_finite());
+
+        // Parallel to the X and Y slabs with the origin exactly on their planes
+        let cube = Object::AxisAlignedBox(AxisAlignedBox::new(Point3::ZERO, Point3::ONE, material()));
+        let hit = intersect(&cube, Point3::new(0.0, 1.0, 5.0), -Vec3::Z).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::Z);
+        assert!(intersect(&cube, Point3::new(0.0, 1.5, 5.0), -Vec3::Z).is_none());
+    }
+
+    #[test]
+    fn plane() {
+        let plane = Object::Plane(Plane::new(Point3::ZERO, Vec3::Y, material()));
+        let hit = intersect(&plane, Point3::new(2.0, 3.0, 1.0), -Vec3::Y).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_near(hit.point, Point3::new(2.0, 0.0, 1.0));
+        assert!(intersect(&plane, Point3::new(0.0, 3.0, 0.0), Vec3::X).is_none());
+        assert!(intersect(&plane, Point3::new(0.0, 3.0, 0.0), Vec3::Y).is_none());
+    }
+
+    #[test]
+    fn quad() {
+        let quad = Object::Quad(Quad::new(Point3::ZERO, 2.0 * Vec3::X, Vec3::Y, material()));
+        let hit = intersect(&quad, Point3::new(1.5, 0.25, 1.0), -Vec3::Z).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::Z);
+        assert_near(hit.uv.extend(0.0), Vec3::new(0.75, 0.25, 0.0));
+        assert!(intersect(&quad, Point3::new(2.5, 0.25, 1.0), -Vec3::Z).is_none());
+        assert!(intersect(&quad, Point3::new(1.0, 1.5, 1.0), -Vec3::Z).is_none());
+    }
+
+    #[test]
+    fn disk() {
+        let disk = Object::Disk(Disk::new(Point3::ZERO, Vec3::Z, 2.0, material()));
+        let hit = intersect(&disk, Point3::new(1.0, 0.0, 1.0), -Vec3::Z).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::Z);
+        assert!((hit.uv.y - 0.5).abs() < 1e-5);
+        assert!((0.0..=1.0).contains(&hit.uv.x));
+        assert!(intersect(&disk, Point3::new(1.5, 1.5, 1.0), -Vec3::Z).is_none());
+    }
+
+    #[test]
+    fn cylinder() {
+        let cylinder = Object::Cylinder(Cylinder::new(Point3::ZERO, 1.0, 2.0, material()));
+        // Side, halfway up
+        let hit = intersect(&cylinder, Point3::new(3.0, 1.0, 0.0), -Vec3::X).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::X);
+        assert!((hit.uv.y - 0.5).abs() < 1e-5);
+        // Top and bottom caps
+        let hit = intersect(&cylinder, Point3::new(0.5, 5.0, 0.5), -Vec3::Y).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::Y);
+        assert_near(hit.uv.extend(0.0), Vec3::new(0.75, 0.75, 0.0));
+        let hit = intersect(&cylinder, Point3::new(0.0, -1.0, 0.0), Vec3::Y).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_near(hit.normal, -Vec3::Y);
+        // From inside, the side is hit from the back
+        let hit = intersect(&cylinder, Point3::new(0.0, 1.0, 0.0), Vec3::X).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!(!hit.front_face);
+        // Above the top, and outside the radius
+        assert!(intersect(&cylinder, Point3::new(3.0, 2.5, 0.0), -Vec3::X).is_none());
+        assert!(intersect(&cylinder, Point3::new(1.5, 5.0, 0.0), -Vec3::Y).is_none());
+    }
+
+    #[test]
+    fn instance() {
+        let prototypes = vec![
+            Object::AxisAlignedBox(AxisAlignedBox::new(Point3::splat(-1.0), Point3::splat(1.0), material())),
+            Object::Quad(Quad::new(Point3::ZERO, Vec3::X, Vec3::Y, material())),
+        ];
+
+        // Stretched along X and moved, with normals still unit length and facing out
+        let transform = Affine3A::from_scale_rotation_translation(Vec3::new(2.0, 1.0, 1.0), glam::Quat::IDENTITY, Vec3::new(5.0, 0.0, 0.0));
+        let stretched = Object::Instance(Instance::new(0, &prototypes[0], transform));
+        let hit = intersect_in(&stretched, &prototypes, Point3::new(5.0, 0.0, 5.0), -Vec3::Z).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert_near(hit.point, Point3::new(5.0, 0.0, 1.0));
+        assert_near(hit.normal, Vec3::Z);
+        let hit = intersect_in(&stretched, &prototypes, Point3::new(10.0, 0.0, 0.0), -Vec3::X).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::X);
+        assert!(intersect_in(&stretched, &prototypes, Point3::new(0.0, 0.0, 5.0), -Vec3::Z).is_none());
+        let aabb = stretched.compute_bounds(0).aabb;
+        assert!(aabb.contains(&Point3::new(6.9, 0.9, 0.9)) && !aabb.contains(&Point3::new(7.5, 0.0, 0.0)));
+
+        // Turned a quarter around Y, so the quad faces along X
+        let turned = Object::Instance(Instance::new(1, &prototypes[1], Affine3A::from_rotation_y(std::f32::consts::FRAC_PI_2)));
+        let hit = intersect_in(&turned, &prototypes, Point3::new(3.0, 0.5, -0.5), -Vec3::X).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::X);
+        assert_near(hit.uv.extend(0.0), Vec3::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn degenerate_lights() {
+        let light = || Material::DiffuseLight(DiffuseLight { emit: Color::ONE });
+        assert!(Object::Quad(Quad::new(Point3::ZERO, Vec3::X, Vec3::Y, light())).is_light());
+        assert!(!Object::Quad(Quad::new(Point3::ZERO, Vec3::X, 2.0 * Vec3::X, light())).is_light());
+        assert!(!Object::Triangle(Triangle::new([Point3::ZERO, Point3::X, 2.0 * Point3::X], light())).is_light());
+        assert!(!Object::Disk(Disk::new(Point3::ZERO, Vec3::Z, 0.0, light())).is_light());
+        let flat_mesh = TriangleMesh::new(vec![Point3::ZERO, Point3::X, 2.0 * Point3::X], vec![], vec![], vec![[0, 1, 2]], light());
+        assert!(!Object::TriangleMesh(flat_mesh).is_light());
+    }
+}
@@ -1,15 +1,27 @@
 use futures::prelude::*;
 use rand::Rng;
 use serde::{Serialize, Deserialize};
+use image::GenericImage;
 use spiral::ChebyshevIterator;
 
+use crate::aov::{Aov, AovSample};
 use crate::camera::Camera;
+use crate::filter::Filter;
+use crate::hdr::HdrImage;
+use crate::object::HitRecord;
 use crate::parallel::ParallelExecutor;
-use crate::scene::Scene;
-use crate::shared::{TRACE_EPSILON, TRACE_INFINITY, Color, Ray, RayQuery, ceil_div, rgb_from_render};
+use crate::sampler::{DIMENSION_LENS, DIMENSION_PIXEL, DIMENSION_TIME, PixelSampler, Sampler};
+use crate::scene::{FOG_OBJECT, Scene};
+use crate::shared::{TRACE_EPSILON, TRACE_INFINITY, Color, Ray, RayQuery, SampleRng, ceil_div, luminance, sample_rng};
 
 const BLOCK_SIZE: u32 = 32;
 
+// With adaptive sampling, the error estimate is checked after each batch of this many samples
+const ADAPTIVE_BATCH: u32 = 8;
+// Pixels darker than this are treated as this bright when estimating their relative error, so
+// noise in dark areas doesn't draw lots of samples
+const ADAPTIVE_LUMINANCE_FLOOR: f32 = 0.1;
+
 /// Coordinates for a block to render
 #[derive(Copy, Clone)]
 #[derive(Serialize, Deserialize)]
@@ -18,6 +30,8 @@ pub struct RenderBlock {
     pub y: u32,
     pub width: u32,
     pub height: u32,
+    // Progressive pass the block is rendered in, always 0 unless rendering progressively
+    pub pass: u32,
 }
 
 /// Generates blocks of up to width,height for an image of width,height
@@ -71,6 +85,7 @@ impl Iterator for ImageBlocker {
             y: y,
             width: x_end - x,
             height: y_end - y,
+            pass: 0,
         };
 
         self.block_index += 1;
@@ -79,37 +94,233 @@ impl Iterator for ImageBlocker {
     }
 }
 
-/// Recursive ray tracing
-fn ray_color(ray: Ray, scene: &Scene, depth: i32) -> Color {
-    if depth <= 0 {
+/// How radiance is estimated along paths
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Integrator {
+    // Only follow scattered rays, relying on them randomly hitting lights
+    #[serde(rename = "naive")]
+    Naive,
+    // Also sample lights directly at diffuse hits, combined with scattering using multiple
+    // importance sampling
+    #[serde(rename = "next-event")]
+    NextEvent,
+}
+
+/// Multiple importance sampling weight for a sample from a strategy with pdf_a, where a strategy
+/// with pdf_b could also have produced it
+fn power_heuristic(pdf_a: f32, pdf_b: f32) -> f32 {
+    let a2 = pdf_a * pdf_a;
+    let b2 = pdf_b * pdf_b;
+    if a2 + b2 == 0.0 {
+        return 0.0;
+    }
+    a2 / (a2 + b2)
+}
+
+/// Radiance arriving at the hit from a randomly chosen light, weighted for combination with
+/// scattering
+fn sample_light(scene: &Scene, ray: &Ray, hit: &HitRecord, rng: &mut SampleRng) -> Color {
+    if scene.lights.is_empty() {
         return Color::ZERO;
     }
+    let light_index = scene.lights[rng.gen_range(0..scene.lights.len())];
+    let direction = scene.objects[light_index].sample_direction(hit.point, ray.time, rng);
 
-    // Intersect scene
+    let bsdf = hit.material.eval(ray, hit, direction, &scene.images);
+    if bsdf == Color::ZERO {
+        return Color::ZERO;
+    }
+
+    // Shadow ray, which must reach the light we sampled
+    let shadow_ray = Ray::new(hit.point, direction, ray.time);
     let query = RayQuery {
-        ray: ray,
+        ray: shadow_ray,
         t_min: TRACE_EPSILON,
         t_max: TRACE_INFINITY,
     };
-    let hit_option = scene.intersect(query);
+    match scene.intersect(query, rng) {
+        Some(light_hit) if light_hit.object == light_index => {
+            let light_pdf = scene.light_pdf(&shadow_ray, &light_hit);
+            if light_pdf <= 0.0 {
+                return Color::ZERO;
+            }
+            let bsdf_pdf = hit.material.pdf(ray, hit, direction, &scene.images);
+            let emitted = light_hit.material.emitted(&light_hit);
+            bsdf * emitted * power_heuristic(light_pdf, bsdf_pdf) / light_pdf
+        },
+        _ => Color::ZERO,
+    }
+}
 
-    // If we hit something
-    if let Some(hit) = hit_option {
-        let scatter_option = hit.material.scatter(&ray, &hit);
+/// Iterative path tracing, tracking the throughput of the path and randomly terminating it with
+/// Russian roulette from roulette_depth bounces onwards. The first hit is recorded into aov, if
+/// given, along with the number of the sample.
+fn ray_color(mut ray: Ray, scene: &Scene, max_depth: u32, roulette_depth: u32, integrator: Integrator, rng: &mut SampleRng, mut aov: Option<(&mut AovSample, u32)>) -> Color {
+    let mut color = Color::ZERO;
+    let mut throughput = Color::ONE;
+    // Density with which the current ray was scattered, if lights were also sampled directly from
+    // its origin
+    let mut bsdf_pdf: Option<f32> = None;
+
+    for depth in 0..max_depth {
+        // Intersect scene
+        let query = RayQuery {
+            ray: ray,
+            t_min: TRACE_EPSILON,
+            t_max: TRACE_INFINITY,
+        };
+        let hit = match scene.intersect(query, rng) {
+            Some(hit) => hit,
+            None => {
+                // Background
+                color += throughput * scene.environment.color(ray.direction);
+                break;
+            },
+        };
+        if let Some((aov, sample)) = aov.take() {
+            record_first_hit(aov, &ray, &hit, scene, sample);
+        }
 
-        // Recurse
-        if let Some(scatter) = scatter_option {
-            return scatter.attenuation
-                * ray_color(scatter.scattered_ray, scene, depth - 1);
+        let mut emitted = hit.material.emitted(&hit);
+        if let Some(bsdf_pdf) = bsdf_pdf {
+            // This light could also have been sampled directly at the previous hit
+            emitted *= power_heuristic(bsdf_pdf, scene.light_pdf(&ray, &hit));
         }
+        color += throughput * emitted;
 
-        return Color::ZERO;
+        let scatter = match hit.material.scatter(&ray, &hit, &scene.images, rng) {
+            Some(scatter) => scatter,
+            None => break,
+        };
+
+        if integrator == Integrator::NextEvent && !hit.material.is_specular() {
+            color += throughput * sample_light(scene, &ray, &hit, rng);
+            bsdf_pdf = Some(hit.material.pdf(&ray, &hit, scatter.scattered_ray.direction, &scene.images));
+        } else {
+            bsdf_pdf = None;
+        }
+
+        throughput *= scatter.attenuation;
+        ray = scatter.scattered_ray;
+
+        // Russian roulette, surviving paths are weighted up to keep the estimate unbiased
+        if depth + 1 >= roulette_depth {
+            let survival = f32::min(throughput.max_element(), 0.95);
+            if rng.gen_range(0.0..1.0) >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
     }
 
-    // Background
-    let unit_direction = ray.direction.normalize();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    return (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0);
+    color
+}
+
+/// Settings for adaptive sampling. Each pixel gets at least samples_per_pixel samples, then more
+/// until the relative error of its mean drops below noise_threshold or it reaches max_samples.
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct AdaptiveSampling {
+    pub max_samples: u32,
+    pub noise_threshold: f32,
+}
+
+/// Running mean and variance of sample luminance, using Welford's algorithm
+#[derive(Default)]
+struct SampleStats {
+    count: u32,
+    mean: f32,
+    m2: f32,
+}
+
+impl SampleStats {
+    fn add(&mut self, c: Color) {
+        let l = luminance(c);
+        self.count += 1;
+        let delta = l - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (l - self.mean);
+    }
+
+    /// Standard error of the mean luminance, relative to the mean
+    fn relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f32;
+        (variance / self.count as f32).sqrt() / self.mean.max(ADAPTIVE_LUMINANCE_FLOOR)
+    }
+}
+
+/// Rendered pixels for an image or block. The beauty pass and AOVs are all linear float RGB.
+#[derive(Clone)]
+pub struct RenderOutput {
+    pub beauty: HdrImage,
+    pub aovs: Vec<(Aov, HdrImage)>,
+}
+
+impl RenderOutput {
+    pub fn new(width: u32, height: u32, aovs: &[Aov]) -> Self {
+        RenderOutput {
+            beauty: HdrImage::new(width, height),
+            aovs: aovs.iter().map(|&aov| (aov, HdrImage::new(width, height))).collect(),
+        }
+    }
+
+    pub fn aov(&self, aov: Aov) -> Option<&HdrImage> {
+        self.aovs.iter().find(|(a, _)| *a == aov).map(|(_, img)| img)
+    }
+
+    /// Merge in a further pass over the same pixels, where weight is the pass's share of all the
+    /// samples so far
+    pub fn accumulate(&mut self, pass: &RenderOutput, weight: f32) {
+        for (pixel, pass_pixel) in self.beauty.pixels_mut().zip(pass.beauty.pixels()) {
+            for c in 0..3 {
+                pixel[c] += (pass_pixel[c] - pixel[c]) * weight;
+            }
+        }
+        for (aov, img) in self.aovs.iter_mut() {
+            if let Some(pass_img) = pass.aov(*aov) {
+                for (pixel, pass_pixel) in img.pixels_mut().zip(pass_img.pixels()) {
+                    pixel.0 = aov.accumulate(pixel.0, pass_pixel.0, weight);
+                }
+            }
+        }
+    }
+
+    /// Copy a rendered block into place
+    pub fn copy_from(&mut self, block: &RenderOutput, x: u32, y: u32) {
+        self.beauty.copy_from(&block.beauty, x, y).unwrap();
+        for (aov, img) in self.aovs.iter_mut() {
+            if let Some(block_img) = block.aov(*aov) {
+                img.copy_from(block_img, x, y).unwrap();
+            }
+        }
+    }
+
+    fn into_raw(self) -> RawOutput {
+        RawOutput {
+            beauty: self.beauty.into_raw(),
+            aovs: self.aovs.into_iter().map(|(aov, img)| (aov, img.into_raw())).collect(),
+        }
+    }
+}
+
+/// RenderOutput as sent back from workers
+#[derive(Serialize, Deserialize)]
+struct RawOutput {
+    beauty: Vec<f32>,
+    aovs: Vec<(Aov, Vec<f32>)>,
+}
+
+impl RawOutput {
+    fn into_output(self, width: u32, height: u32) -> RenderOutput {
+        RenderOutput {
+            beauty: HdrImage::from_raw(width, height, self.beauty).unwrap(),
+            aovs: self.aovs.into_iter().map(|(aov, raw)| (aov, HdrImage::from_raw(width, height, raw).unwrap())).collect(),
+        }
+    }
 }
 
 /// Renderer which generates pixels using the scene and camera, and returns them via a stream
@@ -119,7 +330,16 @@ pub struct Renderer {
     scene: Scene,
     camera: Camera,
     samples_per_pixel: u32,
-    max_depth: i32,
+    max_depth: u32,
+    roulette_depth: u32,
+    integrator: Integrator,
+    sampler: Sampler,
+    filter: Filter,
+    seed: u64,
+    frame: u32,
+    aovs: Vec<Aov>,
+    adaptive: Option<AdaptiveSampling>,
+    pass_samples: Option<u32>,
 }
 
 impl Renderer {
@@ -137,9 +357,81 @@ impl Renderer {
             camera: camera,
             samples_per_pixel: samples_per_pixel,
             max_depth: 50,
+            roulette_depth: 3,
+            integrator: Integrator::NextEvent,
+            sampler: Sampler::default(),
+            filter: Filter::default(),
+            seed: 0,
+            frame: 0,
+            aovs: vec![],
+            adaptive: None,
+            pass_samples: None,
+        }
+    }
+
+    /// Render progressively in passes of pass_samples samples per pixel, with the result so far
+    /// streamed after each pass. Adaptive sampling isn't used when rendering progressively.
+    pub fn set_progressive(&mut self, pass_samples: Option<u32>) {
+        self.pass_samples = pass_samples;
+    }
+
+    /// Number of passes the frame is rendered in
+    pub fn pass_count(&self) -> u32 {
+        match self.pass_samples {
+            Some(n) if n > 0 => ceil_div(self.samples_per_pixel, n).max(1),
+            _ => 1,
         }
     }
 
+    /// The first sample and number of samples per pixel for a pass
+    fn pass_samples(&self, pass: u32) -> (u32, u32) {
+        match self.pass_samples {
+            Some(n) if n > 0 => (pass * n, n.min(self.samples_per_pixel.saturating_sub(pass * n))),
+            _ => (0, self.samples_per_pixel),
+        }
+    }
+
+    /// Enable adaptive sampling, with samples_per_pixel becoming the minimum per pixel
+    pub fn set_adaptive(&mut self, adaptive: Option<AdaptiveSampling>) {
+        self.adaptive = adaptive;
+    }
+
+    /// Set the AOVs to output alongside the beauty pass
+    pub fn set_aovs(&mut self, aovs: &[Aov]) {
+        self.aovs = aovs.to_vec();
+    }
+
+    /// Set the seed and frame number which, along with the pixel and sample, determine all
+    /// random numbers used in rendering
+    pub fn set_seed(&mut self, seed: u64, frame: u32) {
+        self.seed = seed;
+        self.frame = frame;
+    }
+
+    /// Set the maximum number of bounces for a path
+    pub fn set_max_depth(&mut self, max_depth: u32) {
+        self.max_depth = max_depth;
+    }
+
+    /// Set the number of bounces after which paths may be terminated by Russian roulette
+    pub fn set_roulette_depth(&mut self, roulette_depth: u32) {
+        self.roulette_depth = roulette_depth;
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    /// Set how the pixel and lens positions of camera samples are picked
+    pub fn set_sampler(&mut self, sampler: Sampler) {
+        self.sampler = sampler;
+    }
+
+    /// Set the reconstruction filter used to combine samples into pixels
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
     pub fn width(&self) -> u32 {
         self.image_width
     }
@@ -148,20 +440,62 @@ impl Renderer {
         self.image_height
     }
 
-    pub fn render_frame_single(self, pool: &impl ParallelExecutor) -> impl Future<Output=image::RgbImage> {
-        let renderblock = RenderBlock { x: 0, y: 0, width: self.image_width, height: self.image_height };
-        pool.execute(render_block, Ctx {
+    fn block_ctx(&self, renderblock: RenderBlock) -> Ctx {
+        let (first_sample, samples_per_pixel) = self.pass_samples(renderblock.pass);
+        Ctx {
             renderblock,
             image_width: self.image_width,
             image_height: self.image_height,
             scene: self.scene.clone(),
             camera: self.camera.clone(),
-            samples_per_pixel: self.samples_per_pixel,
+            first_sample,
+            samples_per_pixel,
             max_depth: self.max_depth,
-        }).map(move |image| image::RgbImage::from_raw(renderblock.width, renderblock.height, image).unwrap())
+            roulette_depth: self.roulette_depth,
+            integrator: self.integrator,
+            sampler: self.sampler,
+            filter: self.filter,
+            seed: self.seed,
+            frame: self.frame,
+            aovs: self.aovs.clone(),
+            adaptive: if self.pass_count() > 1 { None } else { self.adaptive },
+        }
+    }
+
+    /// Render the frame as a single block, streaming the result so far after each pass. There is
+    /// only one pass unless rendering progressively.
+    pub fn render_frame_single<'a>(self, pool: &'a impl ParallelExecutor) -> impl Stream<Item=RenderOutput> + 'a {
+        let pass_count = self.pass_count();
+        let pass_weights: Vec<f32> = (0..pass_count).map(|pass| self.pass_weight(pass)).collect();
+        futures::stream::iter(0..pass_count)
+            .then(move |pass| {
+                let renderblock = RenderBlock { x: 0, y: 0, width: self.image_width, height: self.image_height, pass };
+                pool.execute(render_block, self.block_ctx(renderblock))
+                    .map(move |raw| (pass, raw.into_output(renderblock.width, renderblock.height)))
+            })
+            .scan(None, move |accumulated: &mut Option<RenderOutput>, (pass, output)| {
+                let output = match accumulated.take() {
+                    None => output,
+                    Some(mut accumulated) => {
+                        accumulated.accumulate(&output, pass_weights[pass as usize]);
+                        accumulated
+                    },
+                };
+                *accumulated = Some(output.clone());
+                future::ready(Some(output))
+            })
+    }
+
+    /// Share of the samples so far which come from a pass
+    fn pass_weight(&self, pass: u32) -> f32 {
+        let (first_sample, samples) = self.pass_samples(pass);
+        samples as f32 / (first_sample + samples).max(1) as f32
     }
 
-    pub fn render_frame_parallel(self, pool: &impl ParallelExecutor) -> impl Stream<Item=(RenderBlock, image::RgbImage)> {
+    /// Render the frame in blocks, streaming each block as it finishes. When rendering
+    /// progressively each pass is completed before the next is started, and blocks hold the
+    /// result of all passes so far.
+    pub fn render_frame_parallel<'a>(self, pool: &'a impl ParallelExecutor) -> impl Stream<Item=(RenderBlock, RenderOutput)> + 'a {
         // Generate blocks to render the image
         let blocker = ImageBlocker::new(self.image_width, self.image_height);
         let block_count_x = blocker.block_count_x as i32;
@@ -183,22 +517,36 @@ impl Renderer {
             spiral_blocks.push(blocks[block_index])
         }
 
-        // Loop blocks in the image blocker and spawn renderblock tasks
-        let futs: futures::stream::FuturesUnordered<_> = spiral_blocks.into_iter().map(|renderblock| {
-            pool.execute(render_block, Ctx {
-                renderblock,
-                image_width: self.image_width,
-                image_height: self.image_height,
-                scene: self.scene.clone(),
-                camera: self.camera.clone(),
-                samples_per_pixel: self.samples_per_pixel,
-                max_depth: self.max_depth,
-            }).map(move |image|
-                (renderblock, image::RgbImage::from_raw(renderblock.width, renderblock.height, image).unwrap())
-            )
-        }).collect();
-
-        futs
+        let pass_count = self.pass_count();
+        let pass_weights: Vec<f32> = (0..pass_count).map(|pass| self.pass_weight(pass)).collect();
+        let block_count = spiral_blocks.len();
+
+        // For each pass, loop blocks in the image blocker and spawn renderblock tasks
+        futures::stream::iter(0..pass_count)
+            .map(move |pass| {
+                let futs: futures::stream::FuturesUnordered<_> = spiral_blocks.iter().enumerate().map(|(block_index, &renderblock)| {
+                    let renderblock = RenderBlock { pass, ..renderblock };
+                    pool.execute(render_block, self.block_ctx(renderblock)).map(move |raw|
+                        (block_index, renderblock, raw.into_output(renderblock.width, renderblock.height))
+                    )
+                }).collect();
+                futs
+            })
+            .flatten()
+            .scan(vec![None; block_count], move |accumulated: &mut Vec<Option<RenderOutput>>, (block_index, renderblock, output)| {
+                if pass_count == 1 {
+                    return future::ready(Some((renderblock, output)));
+                }
+                let output = match accumulated[block_index].take() {
+                    None => output,
+                    Some(mut accumulated) => {
+                        accumulated.accumulate(&output, pass_weights[renderblock.pass as usize]);
+                        accumulated
+                    },
+                };
+                accumulated[block_index] = Some(output.clone());
+                future::ready(Some((renderblock, output)))
+            })
     }
 }
 
@@ -209,37 +557,123 @@ struct Ctx {
     image_height: u32,
     scene: Scene,
     camera: Camera,
+    // Samples to take per pixel, numbered from first_sample
+    first_sample: u32,
     samples_per_pixel: u32,
-    max_depth: i32,
-}
-
-fn render_block(Ctx { renderblock, image_width, image_height, scene, camera, samples_per_pixel, max_depth }: Ctx) -> Vec<u8> {
-    let mut rng = rand::thread_rng();
-    let mut img = image::RgbImage::new(renderblock.width, renderblock.height);
-    img.enumerate_pixels_mut().for_each(|(px, py, pixel)| {
-        // Compute pixel location
-        let x = renderblock.x + px;
-        let y = renderblock.y + py;
-
-        // Set up supersampling
-        let mut color_accum = Color::ZERO;
-        let u_base = x as f32 / (image_width as f32 - 1.0);
-        let v_base = (image_height - y - 1) as f32
-            / (image_height as f32 - 1.0);
-        let u_rand = 1.0 / (image_width as f32 - 1.0);
-        let v_rand = 1.0 / (image_height as f32 - 1.0);
-
-        // Supersample this pixel
-        for _ in 0..samples_per_pixel {
-            let u = u_base + rng.gen_range(0.0..u_rand);
-            let v = v_base + rng.gen_range(0.0..v_rand);
-            let ray = camera.get_ray(u, v);
-            // Start the primary here from here
-            color_accum += ray_color(ray, &scene, max_depth);
-        }
-        color_accum /= samples_per_pixel as f32;
-
-        *pixel = rgb_from_render(color_accum);
-    });
-    img.into_raw()
+    max_depth: u32,
+    roulette_depth: u32,
+    integrator: Integrator,
+    sampler: Sampler,
+    filter: Filter,
+    seed: u64,
+    frame: u32,
+    aovs: Vec<Aov>,
+    adaptive: Option<AdaptiveSampling>,
+}
+
+/// Record the first hit of a camera ray for the AOVs. Depth is the nearest over all samples and the
+/// object id is from the first sample, while normal and albedo are averaged.
+fn record_first_hit(aov: &mut AovSample, ray: &Ray, hit: &HitRecord, scene: &Scene, sample: u32) {
+    let depth = hit.t * ray.direction.length();
+    aov.depth = if aov.depth > 0.0 { aov.depth.min(depth) } else { depth };
+    aov.normal += hit.normal;
+    aov.albedo += hit.material.albedo(hit, &scene.images);
+    if sample == 0 && hit.object != FOG_OBJECT {
+        aov.object_id = hit.object as f32;
+    }
+}
+
+fn render_block(Ctx { renderblock, image_width, image_height, scene, camera, first_sample, samples_per_pixel, max_depth, roulette_depth, integrator, sampler, filter, seed, frame, aovs, adaptive }: Ctx) -> RawOutput {
+    let mut output = RenderOutput::new(renderblock.width, renderblock.height, &aovs);
+    let block_index = |x: u32, y: u32| ((y - renderblock.y) * renderblock.width + (x - renderblock.x)) as usize;
+    let in_block = |x: u32, y: u32| x >= renderblock.x && x < renderblock.x + renderblock.width
+        && y >= renderblock.y && y < renderblock.y + renderblock.height;
+
+    // Filter weighted radiance and the sum of the weights for each pixel of the block
+    let mut weighted = vec![Color::ZERO; (renderblock.width * renderblock.height) as usize];
+    let mut weight_sums = vec![0.0; weighted.len()];
+
+    // Samples of pixels around the block reach into it through the filter, so those pixels are
+    // sampled too. The neighbouring block takes exactly the same samples for them, as samples only
+    // depend on the pixel and sample number.
+    let reach = filter.pixel_reach();
+    let x_range = renderblock.x.saturating_sub(reach)..(renderblock.x + renderblock.width + reach).min(image_width);
+    let y_range = renderblock.y.saturating_sub(reach)..(renderblock.y + renderblock.height + reach).min(image_height);
+
+    for y in y_range {
+        for x in x_range.clone() {
+            // Set up supersampling
+            let pixel_sampler = PixelSampler::new(sampler, seed, frame, x, y, samples_per_pixel);
+            let has_aovs = !aovs.is_empty() && in_block(x, y);
+
+            // Supersample this pixel, adaptively continuing past samples_per_pixel while the error
+            // is too high
+            let max_samples = adaptive.map_or(samples_per_pixel, |a| a.max_samples.max(samples_per_pixel));
+            let mut aov = AovSample::default();
+            let mut stats = SampleStats::default();
+            let mut sample_count = 0;
+            while sample_count < max_samples {
+                if let Some(adaptive) = adaptive {
+                    let extra = sample_count.saturating_sub(samples_per_pixel);
+                    if sample_count >= samples_per_pixel && extra % ADAPTIVE_BATCH == 0
+                        && stats.relative_error() < adaptive.noise_threshold {
+                        break;
+                    }
+                }
+                let sample = first_sample + sample_count;
+                sample_count += 1;
+
+                // Position of the sample in the image, in pixels from the top left
+                let mut rng = sample_rng(seed, frame, x, y, sample);
+                let jitter = pixel_sampler.get_2d(sample, DIMENSION_PIXEL, &mut rng);
+                let (sx, sy) = (x as f32 + jitter.x, y as f32 + jitter.y);
+                let u = sx / (image_width as f32 - 1.0);
+                let v = (image_height as f32 - sy) / (image_height as f32 - 1.0);
+                let lens = pixel_sampler.get_2d(sample, DIMENSION_LENS, &mut rng);
+                // Only cameras with exposure draw a time sample
+                let shutter = if camera.has_exposure() { pixel_sampler.get_2d(sample, DIMENSION_TIME, &mut rng).x } else { 0.0 };
+                // Parts of the image outside the camera's projection are black
+                let color = match camera.get_ray(u, v, lens, shutter) {
+                    // Trace the primary ray from the camera
+                    Some(ray) => ray_color(ray, &scene, max_depth, roulette_depth, integrator, &mut rng, if has_aovs { Some((&mut aov, sample)) } else { None }),
+                    None => Color::ZERO,
+                };
+                if adaptive.is_some() {
+                    stats.add(color);
+                }
+
+                // Splat onto the pixels of the block the filter reaches
+                for ty in y.saturating_sub(reach)..=y + reach {
+                    for tx in x.saturating_sub(reach)..=x + reach {
+                        if !in_block(tx, ty) {
+                            continue;
+                        }
+                        let weight = filter.weight(sx - (tx as f32 + 0.5), sy - (ty as f32 + 0.5));
+                        if weight != 0.0 {
+                            let i = block_index(tx, ty);
+                            weighted[i] += weight * color;
+                            weight_sums[i] += weight;
+                        }
+                    }
+                }
+            }
+
+            if has_aovs {
+                aov.normal /= sample_count as f32;
+                aov.albedo /= sample_count as f32;
+                aov.sample_count = sample_count as f32;
+                for (aov_kind, img) in output.aovs.iter_mut() {
+                    img.put_pixel(x - renderblock.x, y - renderblock.y, image::Rgb(aov.value(*aov_kind)));
+                }
+            }
+        }
+    }
+
+    // Linear radiance, conversion for display happens on output
+    for (px, py, pixel) in output.beauty.enumerate_pixels_mut() {
+        let i = block_index(renderblock.x + px, renderblock.y + py);
+        let color = if weight_sums[i] != 0.0 { weighted[i] / weight_sums[i] } else { Color::ZERO };
+        *pixel = image::Rgb(color.into());
+    }
+    output.into_raw()
 }
@@ -0,0 +1,296 @@
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::blue_noise::{BLUE_NOISE_RANKS, BLUE_NOISE_SIZE};
+use crate::shared::{SampleRng, Vec2, splitmix64};
+
+// Largest f32 below 1, so samples stay in [0, 1)
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// Bases for the scrambled Halton sequence, a pair for each 2D dimension
+const HALTON_PRIMES: [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+/// Strategy for picking the pixel and lens positions and the time of each camera sample.
+/// Everything other than uniform random sampling spreads a pixel's samples more evenly, giving less
+/// noise for the same sample count.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Sampler {
+    // Independent uniform random numbers
+    #[serde(rename = "random")]
+    Random,
+    // Jittered samples, one in each cell of a grid over the pixel
+    #[serde(rename = "stratified")]
+    Stratified,
+    // Halton sequence with random digit scrambling per pixel
+    #[serde(rename = "halton")]
+    Halton,
+    // Sobol sequence with hash based Owen scrambling per pixel
+    #[serde(rename = "sobol")]
+    Sobol,
+    // Sobol sequence shared by all pixels, offset per pixel by blue noise so the remaining error
+    // is spread at high frequencies across the image
+    #[serde(rename = "blue-noise")]
+    BlueNoise,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler::Random
+    }
+}
+
+/// Dimensions of the camera sample taken from the sampler, the rest of the path uses random numbers
+pub const DIMENSION_PIXEL: u32 = 0;
+pub const DIMENSION_LENS: u32 = 1;
+// Only the first coordinate is used
+pub const DIMENSION_TIME: u32 = 2;
+
+/// Sampler set up for the samples of one pixel
+pub struct PixelSampler {
+    sampler: Sampler,
+    x: u32,
+    y: u32,
+    // Scrambling shared by every pixel of the frame
+    frame_key: u64,
+    // Scrambling for this pixel alone
+    pixel_key: u64,
+    // Samples to stratify over, with further samples starting a new set of strata
+    strata_samples: u32,
+}
+
+impl PixelSampler {
+    pub fn new(sampler: Sampler, seed: u64, frame: u32, x: u32, y: u32, strata_samples: u32) -> Self {
+        let frame_key = splitmix64(splitmix64(seed) ^ frame as u64);
+        let pixel_key = splitmix64(splitmix64(frame_key ^ x as u64) ^ y as u64);
+        PixelSampler { sampler, x, y, frame_key, pixel_key, strata_samples: strata_samples.max(1) }
+    }
+
+    /// A 2D sample in [0, 1)^2 for one dimension of sample number `sample`
+    pub fn get_2d(&self, sample: u32, dimension: u32, rng: &mut SampleRng) -> Vec2 {
+        let key = |base: u64| splitmix64(base ^ dimension as u64);
+        match self.sampler {
+            Sampler::Random => Vec2::new(rng.gen(), rng.gen()),
+            Sampler::Stratified => {
+                let side = (self.strata_samples as f32).sqrt() as u32;
+                let strata = side * side;
+                let round = sample / strata;
+                let stratum = permute(sample % strata, strata, splitmix64(key(self.pixel_key) ^ round as u64) as u32);
+                let jitter = Vec2::new(rng.gen(), rng.gen());
+                let cell = Vec2::new((stratum % side) as f32, (stratum / side) as f32);
+                ((cell + jitter) / side as f32).min(Vec2::splat(ONE_MINUS_EPSILON))
+            },
+            Sampler::Halton => {
+                let primes = (2 * dimension as usize) % HALTON_PRIMES.len();
+                let scramble = key(self.pixel_key);
+                Vec2::new(
+                    scrambled_radical_inverse(HALTON_PRIMES[primes], sample, scramble),
+                    scrambled_radical_inverse(HALTON_PRIMES[primes + 1], sample, splitmix64(scramble)),
+                )
+            },
+            Sampler::Sobol => owen_scrambled_sobol(sample, key(self.pixel_key)),
+            Sampler::BlueNoise => {
+                let point = owen_scrambled_sobol(sample, key(self.frame_key));
+                // Read the two offsets from distant parts of the tile, which are uncorrelated
+                let shift = (self.frame_key as u32 ^ dimension.wrapping_mul(0x9e3779b9)) as usize;
+                let offset = Vec2::new(
+                    blue_noise(self.x as usize + shift, self.y as usize + (shift >> 8)),
+                    blue_noise(self.x as usize + (shift >> 16) + BLUE_NOISE_SIZE / 2, self.y as usize + (shift >> 24) + BLUE_NOISE_SIZE / 2),
+                );
+                // Toroidal shift, which keeps the sequence's stratification
+                let shifted = point + offset;
+                (shifted - shifted.floor()).min(Vec2::splat(ONE_MINUS_EPSILON))
+            },
+        }
+    }
+}
+
+/// Index i of a random permutation of 0..l chosen by p, from Kensler's "Correlated Multi-Jittered
+/// Sampling"
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        // Cycle walk until the index lands inside 0..l
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+/// Radical inverse of i in base, with each digit shifted by an amount picked by scramble
+fn scrambled_radical_inverse(base: u32, mut i: u32, scramble: u64) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_k = inv_base;
+    let mut result = 0.0;
+    let mut digit_index = 0u64;
+    // Digits past f32 precision make no difference, but the scrambled ones aren't all zero so
+    // stop once they're too small to matter
+    while inv_base_k > 1e-8 {
+        let digit = i % base;
+        let shift = (splitmix64(scramble ^ digit_index) % base as u64) as u32;
+        result += ((digit + shift) % base) as f64 * inv_base_k;
+        i /= base;
+        inv_base_k *= inv_base;
+        digit_index += 1;
+    }
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// First two dimensions of the Sobol sequence, as 32-bit fixed point
+fn sobol_2d(i: u32) -> (u32, u32) {
+    // The first dimension is the van der Corput sequence
+    let x = i.reverse_bits();
+    let mut y = 0;
+    let mut v = 1 << 31;
+    let mut i = i;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+    }
+    (x, y)
+}
+
+/// Nested uniform (Owen) scramble of a 32-bit fixed point value, using the hash from Burley's
+/// "Practical Hash-based Owen Scrambling"
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// Owen scrambled Sobol point, with the index shuffled too so different scrambles give
+/// uncorrelated sequences
+fn owen_scrambled_sobol(i: u32, scramble: u64) -> Vec2 {
+    let index = nested_uniform_scramble(i, scramble as u32);
+    let (x, y) = sobol_2d(index);
+    let to_float = |v: u32| (v as f32 / 4294967296.0).min(ONE_MINUS_EPSILON);
+    Vec2::new(
+        to_float(nested_uniform_scramble(x, (scramble >> 32) as u32)),
+        to_float(nested_uniform_scramble(y, splitmix64(scramble) as u32)),
+    )
+}
+
+/// Value of the tiling blue noise texture at x,y, uniformly distributed in (0, 1)
+fn blue_noise(x: usize, y: usize) -> f32 {
+    let rank = BLUE_NOISE_RANKS[(y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE];
+    (rank as f32 + 0.5) / (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::sample_rng;
+
+    /// The first count samples of dimension for a pixel
+    fn samples(sampler: Sampler, count: u32, dimension: u32) -> Vec<Vec2> {
+        let pixel = PixelSampler::new(sampler, 7, 0, 3, 5, count);
+        let mut rng = sample_rng(7, 0, 3, 5, 0);
+        (0..count).map(|i| pixel.get_2d(i, dimension, &mut rng)).collect()
+    }
+
+    /// Whether exactly one sample falls in each cell of a columns by rows grid
+    fn one_per_cell(samples: &[Vec2], columns: u32, rows: u32) -> bool {
+        let mut cells = vec![0; (columns * rows) as usize];
+        for p in samples {
+            cells[(p.y * rows as f32) as usize * columns as usize + (p.x * columns as f32) as usize] += 1;
+        }
+        cells.iter().all(|&n| n == 1)
+    }
+
+    #[test]
+    fn samples_in_unit_square() {
+        let samplers = [Sampler::Random, Sampler::Stratified, Sampler::Halton, Sampler::Sobol, Sampler::BlueNoise];
+        for &sampler in samplers.iter() {
+            for dimension in [DIMENSION_PIXEL, DIMENSION_LENS, DIMENSION_TIME].iter() {
+                for p in samples(sampler, 256, *dimension) {
+                    assert!((0.0..1.0).contains(&p.x) && (0.0..1.0).contains(&p.y), "{:?} gave {:?}", sampler, p);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified() {
+        for dimension in 0..3 {
+            assert!(one_per_cell(&samples(Sampler::Stratified, 16, dimension), 4, 4));
+        }
+        // Counts that aren't square use the largest square grid that fits
+        assert!(one_per_cell(&samples(Sampler::Stratified, 20, 0)[..16], 4, 4));
+    }
+
+    #[test]
+    fn halton() {
+        // The first 2^i * 3^j points of bases 2 and 3 fall one in each cell of a 2^i by 3^j grid
+        let points = samples(Sampler::Halton, 36, DIMENSION_PIXEL);
+        assert!(one_per_cell(&points[..6], 2, 3));
+        assert!(one_per_cell(&points, 4, 9));
+    }
+
+    #[test]
+    fn sobol() {
+        // Each power of two block of points is a (0, m, 2)-net, so falls one in each cell of every
+        // grid of that many cells with power of two sides
+        for dimension in 0..3 {
+            let points = samples(Sampler::Sobol, 64, dimension);
+            for block in points.chunks(16) {
+                for &(columns, rows) in [(1, 16), (2, 8), (4, 4), (8, 2), (16, 1)].iter() {
+                    assert!(one_per_cell(block, columns, rows), "{}x{} grid", columns, rows);
+                }
+            }
+            assert!(one_per_cell(&points, 8, 8));
+        }
+    }
+
+    #[test]
+    fn blue_noise_values() {
+        // Every rank appears once, so the values are evenly spread
+        let mut values: Vec<f32> = (0..BLUE_NOISE_SIZE * BLUE_NOISE_SIZE)
+            .map(|i| blue_noise(i % BLUE_NOISE_SIZE, i / BLUE_NOISE_SIZE))
+            .collect();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (i, &value) in values.iter().enumerate() {
+            assert_eq!(value, (i as f32 + 0.5) / values.len() as f32);
+        }
+        assert_eq!(blue_noise(3, 5), blue_noise(3 + BLUE_NOISE_SIZE, 5 + 2 * BLUE_NOISE_SIZE));
+    }
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for &l in [1, 5, 16, 100].iter() {
+            let mut values: Vec<u32> = (0..l).map(|i| permute(i, l, 0x1234567)).collect();
+            values.sort_unstable();
+            assert!(values.iter().copied().eq(0..l));
+        }
+    }
+}
@@ -1,22 +1,137 @@
-use crate::object::{HitRecord, HittableBounds, RayHittable, Sphere};
-use crate::shared::RayQuery;
+use crate::hdr::HdrImage;
+use crate::material::Material;
+use crate::object::{HitRecord, HittableBounds, Object, RayHittable};
+use crate::shared::{Color, Ray, RayQuery, SampleRng, Vec3};
+use crate::texture::ImageTexture;
 
 use bvh::bvh::BVH;
+use rand::Rng;
 use serde::{Serialize, Deserialize};
 
+/// Object index of hits in the scene's fog, which isn't an object
+pub const FOG_OBJECT: usize = usize::MAX;
+
+/// Radiance arriving from rays which leave the scene
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub enum Environment {
+    // White to blue sky gradient
+    Gradient,
+    Solid(Color),
+    Map(EnvironmentMap),
+}
+
+impl Environment {
+    /// Radiance from the given direction
+    pub fn color(&self, direction: Vec3) -> Color {
+        match self {
+            Environment::Gradient => {
+                let unit_direction = direction.normalize();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+            },
+            Environment::Solid(color) => *color,
+            Environment::Map(map) => map.color(direction),
+        }
+    }
+}
+
+/// An equirectangular (latitude/longitude) environment image. The center of the image is in
+/// the -Z direction and the top is +Y.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    // Rotation about the Y axis in radians
+    pub rotation: f32,
+    // Multiplier for the radiance of the image
+    pub intensity: f32,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: &HdrImage, rotation: f32, intensity: f32) -> Self {
+        EnvironmentMap {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|p| Color::new(p[0], p[1], p[2])).collect(),
+            rotation: rotation,
+            intensity: intensity,
+        }
+    }
+
+    /// Texel which wraps horizontally and clamps vertically
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.pixels[y * self.width as usize + x]
+    }
+
+    /// Bilinearly filtered radiance from the given direction
+    pub fn color(&self, direction: Vec3) -> Color {
+        let d = direction.normalize();
+        let (sin, cos) = self.rotation.sin_cos();
+        let x = cos * d.x + sin * d.z;
+        let z = -sin * d.x + cos * d.z;
+
+        let u = 0.5 + f32::atan2(x, -z) / (2.0 * std::f32::consts::PI);
+        let v = f32::acos(d.y.clamp(-1.0, 1.0)) / std::f32::consts::PI;
+
+        // Texel centers are at half integer coordinates
+        let px = u * self.width as f32 - 0.5;
+        let py = v * self.height as f32 - 0.5;
+        let (x0, y0) = (px.floor(), py.floor());
+        let (fx, fy) = (px - x0, py - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+        (top * (1.0 - fy) + bottom * fy) * self.intensity
+    }
+}
+
+/// Homogeneous medium filling the space between the surfaces of the scene. Rays which leave the
+/// scene pass through it unaffected, so the environment can still be seen and light the scene.
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Fog {
+    // Chance of scattering per unit distance
+    pub density: f32,
+    // Material::Medium giving the albedo and phase function
+    pub material: Material,
+}
+
 /// Basic scene which holds objects and a BVH
 #[derive(Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Scene {
     // List of hittables
-    //pub objects: Vec<Box<dyn RayHittable>>,
-    pub objects: Vec<Sphere>,
+    pub objects: Vec<Object>,
 
     // List of bounds for hittables
     pub bounds: Vec<HittableBounds>,
 
     // Acceleration structure
     pub bvh: Option<BVH>,
+
+    // Indices of objects without finite bounds, such as planes, which are tested outside the BVH
+    pub unbounded: Vec<usize>,
+
+    // Indices of emissive objects, which are sampled directly
+    pub lights: Vec<usize>,
+
+    // Light from outside the scene
+    pub environment: Environment,
+
+    // Images referenced by textures
+    pub images: Vec<ImageTexture>,
+
+    // Participating medium between the objects
+    pub fog: Option<Fog>,
+
+    // Objects which are only placed in the scene by instances
+    pub prototypes: Vec<Object>,
 }
 
 impl Scene {
@@ -25,49 +140,101 @@ impl Scene {
             objects: Vec::new(),
             bounds: Vec::new(),
             bvh: None,
+            unbounded: Vec::new(),
+            lights: Vec::new(),
+            environment: Environment::Gradient,
+            images: Vec::new(),
+            fog: None,
+            prototypes: Vec::new(),
         }
     }
 
     pub fn build_bvh(&mut self) {
         // Compute bounds
         for (i, hittable) in self.objects.iter().enumerate() {
-            self.bounds.push(hittable.compute_bounds(i));
+            if hittable.is_bounded() {
+                self.bounds.push(hittable.compute_bounds(i));
+            } else {
+                self.unbounded.push(i);
+            }
+        }
+        // Build BVH. The bvh crate can't build over no shapes.
+        if !self.bounds.is_empty() {
+            self.bvh = Some(BVH::build(&mut self.bounds));
+        }
+        // Find lights
+        self.lights = self.objects.iter().enumerate()
+            .filter(|(_, o)| o.is_light())
+            .map(|(i, _)| i)
+            .collect();
+    }
+
+    /// Solid angle density with which light sampling from the origin of ray would produce the hit
+    pub fn light_pdf(&self, ray: &Ray, hit: &HitRecord) -> f32 {
+        if !matches!(hit.material, Material::DiffuseLight(_)) || self.lights.is_empty() {
+            return 0.0;
         }
-        // Build BVH
-        self.bvh = Some(BVH::build(&mut self.bounds));
+        self.objects[hit.object].pdf_value(ray.origin, ray.time, hit) / self.lights.len() as f32
     }
 
-    /// Return the closest intersection (or None) in the scene using the ray
-    pub fn intersect(&self, mut query: RayQuery) -> Option<HitRecord> {
+    /// Return the closest intersection (or None) in the scene using the ray. Hits in media are
+    /// random, so rng is used to sample where the ray scatters.
+    pub fn intersect(&self, mut query: RayQuery, rng: &mut SampleRng) -> Option<HitRecord> {
         let mut closest_hit_option: Option<HitRecord> = None;
 
+        // Test unbounded objects first, so their hits shorten the ray through the BVH
+        for &index in &self.unbounded {
+            self.intersect_object(index, &mut query, &mut closest_hit_option, rng);
+        }
+
         if let Some(bvh) = &self.bvh {
             // Traverse the BVH
-            let bvh_ray = bvh::ray::Ray::new(
-                query.ray.origin,
-                query.ray.direction,
-            );
+            let bvh_ray = query.ray.to_bvh();
             let hit_bounds = bvh.traverse_iterator(&bvh_ray, &self.bounds);
 
             // Iterate over hit objects to find closest
             for bounds in hit_bounds {
-                let obj = &self.objects[bounds.hittable_index];
-                let hit_option = obj.intersect(query);
-                if hit_option.is_some() {
-                    // Shorten the ray
-                    query.t_max = f32::min(query.t_max, hit_option.as_ref().unwrap().t);
-                }
-                if closest_hit_option.is_none() {
-                    closest_hit_option = hit_option;
-                } else if hit_option.is_some() {
-                    let closest_hit = closest_hit_option.as_ref().unwrap();
-                    let hit = hit_option.as_ref().unwrap();
-                    if hit.t < closest_hit.t {
-                        closest_hit_option = hit_option;
-                    }
-                }
+                self.intersect_object(bounds.hittable_index, &mut query, &mut closest_hit_option, rng);
+            }
+        }
+
+        // Scatter in the fog before reaching the surface
+        if let (Some(fog), Some(closest_hit)) = (&self.fog, &closest_hit_option) {
+            let distance = -(1.0 - rng.gen::<f32>()).ln() / fog.density;
+            let t = distance / query.ray.direction.length();
+            if t > query.t_min && t < closest_hit.t {
+                let mut hit = HitRecord::in_medium(query.ray, t, fog.material);
+                hit.object = FOG_OBJECT;
+                return Some(hit);
             }
         }
         return closest_hit_option;
     }
+
+    /// Intersect one object, replacing closest_hit_option if it's hit closer
+    fn intersect_object(
+        &self,
+        index: usize,
+        query: &mut RayQuery,
+        closest_hit_option: &mut Option<HitRecord>,
+        rng: &mut SampleRng,
+    ) {
+        let hit_option = self.objects[index].intersect(*query, &self.prototypes, rng).map(|mut hit| {
+            hit.object = index;
+            hit
+        });
+        if hit_option.is_some() {
+            // Shorten the ray
+            query.t_max = f32::min(query.t_max, hit_option.as_ref().unwrap().t);
+        }
+        if closest_hit_option.is_none() {
+            *closest_hit_option = hit_option;
+        } else if hit_option.is_some() {
+            let closest_hit = closest_hit_option.as_ref().unwrap();
+            let hit = hit_option.as_ref().unwrap();
+            if hit.t < closest_hit.t {
+                *closest_hit_option = hit_option;
+            }
+        }
+    }
 }
@@ -0,0 +1,832 @@
+//! Declarative JSON scene files. A scene file looks like:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "camera": { "lookfrom": [13, 2, 3], "lookat": [0, 0, 0], "vfov": 20, "aperture": 0.1,
+//!               "shutter_open": 0, "shutter_close": 1 },
+//!   "render": { "width": 640, "height": 360, "samples_per_pixel": 64, "max_depth": 50, "roulette_depth": 3,
+//!               "max_samples": 512, "noise_threshold": 0.02, "pass_samples": 8, "sampler": "sobol",
+//!               "filter": { "kernel": "mitchell", "radius": 2 },
+//!               "tonemap": { "operator": "aces", "exposure": 0.5, "oetf": "srgb" } },
+//!   "environment": { "type": "map", "path": "sky.hdr", "rotation": 90, "intensity": 1.5 },
+//!   "textures": {
+//!     "tiles": { "type": "checker", "even": [0.2, 0.3, 0.1], "odd": [0.9, 0.9, 0.9], "size": 0.5 },
+//!     "earth": { "type": "image", "path": "earthmap.png", "wrap": "repeat" }
+//!   },
+//!   "materials": {
+//!     "ground": { "type": "lambertian", "albedo": "tiles" },
+//!     "gold": { "type": "metallic_roughness", "base_color": [1.0, 0.78, 0.34], "metallic": 1, "roughness": 0.3 },
+//!     "marble": { "type": "lambertian", "albedo": { "type": "noise", "kind": "marble", "scale": 4 } },
+//!     "smoke": { "type": "medium", "albedo": [0.9, 0.9, 0.9], "anisotropy": 0.3 }
+//!   },
+//!   "fog": { "density": 0.01, "material": "smoke" },
+//!   "prototypes": {
+//!     "pebble": { "type": "sphere", "center": [0, 0, 0], "radius": 0.2, "material": "marble" }
+//!   },
+//!   "objects": [
+//!     { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0], "material": "ground" },
+//!     { "type": "quad", "corner": [-1, 5, -1], "u": [2, 0, 0], "v": [0, 0, 2],
+//!       "material": { "type": "diffuse_light", "emit": [4, 4, 4] } },
+//!     { "type": "box", "min": [5, 0, -1], "max": [6, 1, 0], "material": "gold" },
+//!     { "type": "cylinder", "center": [-6, 0, 2], "radius": 0.5, "height": 2, "material": "gold" },
+//!     { "type": "disk", "center": [-6, 2.01, 2], "normal": [0, 1, 0], "radius": 0.5, "material": "marble" },
+//!     { "type": "sphere", "center": [0, 1, 0], "radius": 1, "material": { "type": "dielectric", "ir": 1.5 } },
+//!     { "type": "sphere", "center": [-4, 1, 0], "radius": 1, "material": { "type": "lambertian", "albedo": "earth" } },
+//!     { "type": "obj", "path": "teapot.obj" },
+//!     { "type": "instance", "prototype": "pebble",
+//!       "transform": { "scale": [2, 1, 1], "rotate": [0, 45, 0], "translate": [3, 0.2, 3] } },
+//!     { "type": "moving", "translation": [0, 0.5, 0], "time0": 0, "time1": 1,
+//!       "object": { "type": "sphere", "center": [2, 0.5, 2], "radius": 0.5, "material": "marble" } },
+//!     { "type": "constant_medium", "boundary": { "type": "sphere", "center": [4, 1, 0], "radius": 1 }, "density": 2,
+//!       "material": "smoke" }
+//!   ]
+//! }
+//! ```
+//!
+//! The camera's `projection` defaults to `{ "type": "perspective" }`, and can instead be
+//! `{ "type": "orthographic", "height": 10 }`, `{ "type": "fisheye", "fov": 180 }` or
+//! `{ "type": "equirectangular" }`, which ignore `vfov` and the lens.
+//!
+//! Materials are referenced by name or given inline. Paths are relative to the scene file. Prototypes
+//! aren't rendered themselves, only where they're placed by instances, and instances of lights
+//! aren't sampled directly.
+
+use glam::{Affine3A, Quat};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::camera::CameraSettings;
+use crate::denoise::Denoiser;
+use crate::filter::Filter;
+use crate::hdr;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Medium, Metal, MetallicRoughness};
+use crate::obj::{self, ObjError};
+use crate::object::{
+    AxisAlignedBox, ConstantMedium, Cylinder, Disk, Instance, Moving, Object, Plane, Quad, Sphere, Triangle, TriangleMesh,
+};
+use crate::render::AdaptiveSampling;
+use crate::sampler::Sampler;
+use crate::scene::{Environment, EnvironmentMap, Fog, Scene};
+use crate::shared::{Color, Point3, Vec2, Vec3};
+use crate::texture::{Checker, ImageRef, ImageTexture, Noise, NoiseKind, Texture, Wrap};
+use crate::tonemap::ToneMap;
+
+/// The scene file version understood by this build
+pub const SCENE_FILE_VERSION: u32 = 1;
+
+/// A scene with the camera and settings to render it
+pub struct SceneDescription {
+    pub scene: Scene,
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+}
+
+/// Render settings from a scene file, where unset values use the renderer defaults
+#[derive(Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderSettings {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples_per_pixel: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub roulette_depth: Option<u32>,
+    pub sampler: Option<Sampler>,
+    pub filter: Option<Filter>,
+    // Adaptive sampling is enabled by setting max_samples, with samples_per_pixel as the minimum
+    pub max_samples: Option<u32>,
+    pub noise_threshold: Option<f32>,
+    // Render progressively in passes of this many samples per pixel, which replaces adaptive sampling
+    pub pass_samples: Option<u32>,
+    pub tonemap: Option<ToneMap>,
+    // Denoise the finished render, e.g. `"denoise": {}` for the default settings
+    pub denoise: Option<Denoiser>,
+}
+
+/// Noise threshold used when only max_samples is given
+pub const DEFAULT_NOISE_THRESHOLD: f32 = 0.05;
+
+impl RenderSettings {
+    pub fn adaptive(&self) -> Option<AdaptiveSampling> {
+        self.max_samples.map(|max_samples| AdaptiveSampling {
+            max_samples,
+            noise_threshold: self.noise_threshold.unwrap_or(DEFAULT_NOISE_THRESHOLD),
+        })
+    }
+}
+
+/// Error from loading a scene file
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(PathBuf, std::io::Error),
+    Json(PathBuf, serde_json::Error),
+    UnsupportedVersion(PathBuf, u32),
+    // An error in a specific entry of the file, e.g. "objects[3]"
+    Entry { path: PathBuf, entry: String, message: String },
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            SceneFileError::Json(path, e) => write!(f, "{}: {}", path.display(), e),
+            SceneFileError::UnsupportedVersion(path, version) => write!(
+                f, "{}: unsupported scene file version {} (expected {})", path.display(), version, SCENE_FILE_VERSION,
+            ),
+            SceneFileError::Entry { path, entry, message } => write!(f, "{}: {}: {}", path.display(), entry, message),
+            SceneFileError::Obj(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+/// Top level of the file. Textures, materials and objects are kept as JSON values at first so that each
+/// can be parsed separately, with errors naming the entry.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    version: u32,
+    camera: CameraSettings,
+    #[serde(default)]
+    render: RenderSettings,
+    #[serde(default)]
+    environment: EnvironmentDesc,
+    #[serde(default)]
+    textures: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    materials: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    prototypes: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    objects: Vec<serde_json::Value>,
+    fog: Option<FogDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid { color: Color },
+    Checker {
+        even: Color,
+        odd: Color,
+        #[serde(default = "default_checker_size")]
+        size: f32,
+    },
+    Noise {
+        #[serde(default = "default_noise_kind")]
+        kind: NoiseKind,
+        #[serde(default = "default_noise_color")]
+        color: Color,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+        #[serde(default = "default_noise_depth")]
+        depth: u32,
+    },
+    // .hdr and .pfm images hold linear values, other formats are taken to be sRGB encoded
+    Image {
+        path: PathBuf,
+        #[serde(default = "default_wrap")]
+        wrap: Wrap,
+    },
+}
+
+fn default_checker_size() -> f32 {
+    1.0
+}
+
+fn default_noise_kind() -> NoiseKind {
+    NoiseKind::Perlin
+}
+
+fn default_noise_color() -> Color {
+    Color::ONE
+}
+
+fn default_noise_scale() -> f32 {
+    1.0
+}
+
+fn default_noise_depth() -> u32 {
+    7
+}
+
+fn default_wrap() -> Wrap {
+    Wrap::Repeat
+}
+
+/// A texture given as a plain colour, by name or inline
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color(Color),
+    Named(String),
+    Inline(serde_json::Value),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian { albedo: TextureRef },
+    Metal { albedo: TextureRef, #[serde(default)] fuzz: f32 },
+    Dielectric { ir: f32 },
+    DiffuseLight { emit: Color },
+    // glTF's metallic-roughness model, with the same defaults
+    MetallicRoughness {
+        #[serde(default = "default_base_color")]
+        base_color: TextureRef,
+        #[serde(default = "default_factor")]
+        metallic: f32,
+        #[serde(default = "default_factor")]
+        roughness: f32,
+    },
+    // Scattering inside a constant_medium or the fog
+    Medium {
+        #[serde(default = "default_base_color")]
+        albedo: TextureRef,
+        #[serde(default)]
+        anisotropy: f32,
+    },
+}
+
+fn default_base_color() -> TextureRef {
+    TextureRef::Color(Color::ONE)
+}
+
+fn default_factor() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentDesc {
+    Gradient,
+    Solid { color: Color },
+    // Equirectangular .hdr or .pfm image, rotated about the Y axis in degrees
+    Map {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+impl Default for EnvironmentDesc {
+    fn default() -> Self {
+        EnvironmentDesc::Gradient
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FogDesc {
+    density: f32,
+    // A medium material, by name or inline
+    material: serde_json::Value,
+}
+
+/// A material given by name or inline
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaterialRef {
+    Named(String),
+    Inline(serde_json::Value),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: Point3,
+        radius: f32,
+        material: MaterialRef,
+    },
+    Triangle {
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[Vec2; 3]>,
+        material: MaterialRef,
+    },
+    Mesh {
+        positions: Vec<Point3>,
+        #[serde(default)]
+        normals: Vec<Vec3>,
+        #[serde(default)]
+        uvs: Vec<Vec2>,
+        indices: Vec<[u32; 3]>,
+        material: MaterialRef,
+    },
+    // Infinite plane through point
+    Plane {
+        point: Point3,
+        normal: Vec3,
+        material: MaterialRef,
+    },
+    // Parallelogram from corner along edges u and v
+    Quad {
+        corner: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: MaterialRef,
+    },
+    Disk {
+        center: Point3,
+        normal: Vec3,
+        radius: f32,
+        material: MaterialRef,
+    },
+    // Axis aligned box between corners min and max
+    Box {
+        min: Point3,
+        max: Point3,
+        material: MaterialRef,
+    },
+    // Capped cylinder standing on the Y axis from its base center
+    Cylinder {
+        center: Point3,
+        radius: f32,
+        height: f32,
+        material: MaterialRef,
+    },
+    Obj {
+        path: PathBuf,
+        // Used for faces without an MTL material
+        material: Option<MaterialRef>,
+    },
+    ConstantMedium {
+        // Any single object, whose material can be left out as it takes the medium's
+        boundary: serde_json::Value,
+        density: f32,
+        // A medium material, by name or inline
+        material: serde_json::Value,
+    },
+    // A transformed copy of an object from the file's prototypes
+    Instance {
+        prototype: String,
+        #[serde(default)]
+        transform: TransformDesc,
+    },
+    // Any single object, moved by translation between time0 and time1
+    Moving {
+        object: serde_json::Value,
+        translation: Vec3,
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+    },
+}
+
+fn default_time1() -> f32 {
+    1.0
+}
+
+/// Scale, then rotation about the X, Y and Z axes in turn by angles in degrees, then translation
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TransformDesc {
+    scale: ScaleDesc,
+    rotate: Vec3,
+    translate: Vec3,
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        TransformDesc {
+            scale: ScaleDesc::Uniform(1.0),
+            rotate: Vec3::ZERO,
+            translate: Vec3::ZERO,
+        }
+    }
+}
+
+/// A scale given as one number for all axes or one per axis
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f32),
+    PerAxis(Vec3),
+}
+
+impl TransformDesc {
+    fn to_affine(&self) -> Affine3A {
+        let scale = match self.scale {
+            ScaleDesc::Uniform(s) => Vec3::splat(s),
+            ScaleDesc::PerAxis(s) => s,
+        };
+        let r = self.rotate * std::f32::consts::PI / 180.0;
+        let rotation = Quat::from_rotation_z(r.z) * Quat::from_rotation_y(r.y) * Quat::from_rotation_x(r.x);
+        Affine3A::from_scale_rotation_translation(scale, rotation, self.translate)
+    }
+}
+
+/// State while converting the file into a scene
+struct Loader<'a> {
+    path: &'a Path,
+    textures: BTreeMap<String, Texture>,
+    materials: BTreeMap<String, Material>,
+    // Images loaded for textures, which become the scene's image table
+    images: Vec<ImageTexture>,
+    // Prototypes loaded so far, which become the scene's prototypes, with their indices by name
+    prototypes: Vec<Object>,
+    prototype_names: BTreeMap<String, usize>,
+}
+
+impl<'a> Loader<'a> {
+    fn entry_error(&self, entry: &str, message: impl fmt::Display) -> SceneFileError {
+        SceneFileError::Entry { path: self.path.to_owned(), entry: entry.to_owned(), message: message.to_string() }
+    }
+
+    fn load_environment(&self, desc: EnvironmentDesc) -> Result<Environment, SceneFileError> {
+        Ok(match desc {
+            EnvironmentDesc::Gradient => Environment::Gradient,
+            EnvironmentDesc::Solid { color } => Environment::Solid(color),
+            EnvironmentDesc::Map { path, rotation, intensity } => {
+                let path = self.resolve_path(&path);
+                let image = hdr::load(&path)
+                    .map_err(|e| self.entry_error("environment", format!("{}: {}", path.display(), e)))?;
+                Environment::Map(EnvironmentMap::new(&image, rotation.to_radians(), intensity))
+            },
+        })
+    }
+
+    /// Paths in the file are relative to the file
+    fn resolve_path(&self, path: &Path) -> PathBuf {
+        self.path.parent().unwrap_or_else(|| Path::new(".")).join(path)
+    }
+
+    fn load_image(&mut self, entry: &str, path: &Path) -> Result<usize, SceneFileError> {
+        let path = self.resolve_path(path);
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        let image = match extension.as_deref() {
+            Some("hdr") | Some("pfm") => hdr::load(&path).map(|image| ImageTexture::new(&image)).map_err(|e| e.to_string()),
+            _ => image::open(&path).map_err(|e| e.to_string()).and_then(|image| match image.to_rgb8() {
+                image if image.width() == 0 || image.height() == 0 => Err("image has no pixels".to_owned()),
+                image => Ok(ImageTexture::from_srgb(&image)),
+            }),
+        };
+        let image = image.map_err(|e| self.entry_error(entry, format!("{}: {}", path.display(), e)))?;
+        self.images.push(image);
+        Ok(self.images.len() - 1)
+    }
+
+    fn parse_texture(&mut self, entry: &str, value: serde_json::Value) -> Result<Texture, SceneFileError> {
+        let desc: TextureDesc = serde_json::from_value(value).map_err(|e| self.entry_error(entry, e))?;
+        Ok(match desc {
+            TextureDesc::Solid { color } => Texture::Solid(color),
+            TextureDesc::Checker { even, odd, size } => {
+                if !(size > 0.0) {
+                    return Err(self.entry_error(entry, "checker size must be positive"));
+                }
+                Texture::Checker(Checker { even, odd, size })
+            },
+            TextureDesc::Noise { kind, color, scale, depth } => Texture::Noise(Noise { kind, color, scale, depth }),
+            TextureDesc::Image { path, wrap } => Texture::Image(ImageRef { index: self.load_image(entry, &path)?, wrap }),
+        })
+    }
+
+    fn resolve_texture(&mut self, entry: &str, texture: TextureRef) -> Result<Texture, SceneFileError> {
+        match texture {
+            TextureRef::Color(color) => Ok(Texture::Solid(color)),
+            TextureRef::Named(name) => self.textures.get(&name).copied()
+                .ok_or_else(|| self.entry_error(entry, format!("unknown texture '{}'", name))),
+            TextureRef::Inline(value) => self.parse_texture(entry, value),
+        }
+    }
+
+    fn parse_material(&mut self, entry: &str, value: serde_json::Value) -> Result<Material, SceneFileError> {
+        let desc: MaterialDesc = serde_json::from_value(value).map_err(|e| self.entry_error(entry, e))?;
+        let albedo_entry = format!("{}.albedo", entry);
+        Ok(match desc {
+            MaterialDesc::Lambertian { albedo } => Material::Lambertian(Lambertian {
+                albedo: self.resolve_texture(&albedo_entry, albedo)?,
+            }),
+            MaterialDesc::Metal { albedo, fuzz } => Material::Metal(Metal {
+                albedo: self.resolve_texture(&albedo_entry, albedo)?,
+                fuzz,
+            }),
+            MaterialDesc::Dielectric { ir } => Material::Dielectric(Dielectric { ir }),
+            MaterialDesc::DiffuseLight { emit } => Material::DiffuseLight(DiffuseLight { emit }),
+            MaterialDesc::MetallicRoughness { base_color, metallic, roughness } => {
+                if !(0.0..=1.0).contains(&metallic) || !(0.0..=1.0).contains(&roughness) {
+                    return Err(self.entry_error(entry, "metallic and roughness must be between 0 and 1"));
+                }
+                Material::MetallicRoughness(MetallicRoughness {
+                    base_color: self.resolve_texture(&format!("{}.base_color", entry), base_color)?,
+                    metallic,
+                    roughness,
+                })
+            },
+            MaterialDesc::Medium { albedo, anisotropy } => {
+                if !(anisotropy > -1.0 && anisotropy < 1.0) {
+                    return Err(self.entry_error(entry, "medium anisotropy must be between -1 and 1"));
+                }
+                Material::Medium(Medium {
+                    albedo: self.resolve_texture(&albedo_entry, albedo)?,
+                    anisotropy,
+                })
+            },
+        })
+    }
+
+    /// Resolve the material of a medium, which must be a medium material, and check its density
+    fn resolve_medium(&mut self, entry: &str, density: f32, material: serde_json::Value) -> Result<Material, SceneFileError> {
+        if !(density > 0.0) {
+            return Err(self.entry_error(entry, "medium density must be positive"));
+        }
+        let material = serde_json::from_value(material).map_err(|e| self.entry_error(entry, e))?;
+        match self.resolve_material(entry, material)? {
+            material @ Material::Medium(_) => Ok(material),
+            _ => Err(self.entry_error(entry, "material of a medium must have type medium")),
+        }
+    }
+
+    fn resolve_material(&mut self, entry: &str, material: MaterialRef) -> Result<Material, SceneFileError> {
+        match material {
+            MaterialRef::Named(name) => self.materials.get(&name).copied()
+                .ok_or_else(|| self.entry_error(entry, format!("unknown material '{}'", name))),
+            MaterialRef::Inline(value) => self.parse_material(&format!("{}.material", entry), value),
+        }
+    }
+
+    fn load_object(&mut self, entry: &str, desc: ObjectDesc, scene: &mut Scene) -> Result<(), SceneFileError> {
+        match desc {
+            ObjectDesc::Sphere { center, radius, material } => {
+                if !(radius > 0.0) {
+                    return Err(self.entry_error(entry, "sphere radius must be positive"));
+                }
+                let material = self.resolve_material(entry, material)?;
+                scene.objects.push(Object::Sphere(Sphere::new(center, radius, material)));
+            },
+            ObjectDesc::Triangle { vertices, normals, uvs, material } => {
+                let material = self.resolve_material(entry, material)?;
+                let mut triangle = Triangle::new(vertices, material);
+                if let Some(normals) = normals {
+                    triangle = triangle.with_normals(normals);
+                }
+                if let Some(uvs) = uvs {
+                    triangle = triangle.with_uvs(uvs);
+                }
+                scene.objects.push(Object::Triangle(triangle));
+            },
+            ObjectDesc::Mesh { positions, normals, uvs, indices, material } => {
+                if !normals.is_empty() && normals.len() != positions.len() {
+                    return Err(self.entry_error(entry, "mesh must have no normals or one per position"));
+                }
+                if !uvs.is_empty() && uvs.len() != positions.len() {
+                    return Err(self.entry_error(entry, "mesh must have no uvs or one per position"));
+                }
+                if indices.is_empty() {
+                    return Err(self.entry_error(entry, "mesh must have at least one triangle"));
+                }
+                if let Some(i) = indices.iter().flatten().find(|&&i| i as usize >= positions.len()) {
+                    return Err(self.entry_error(entry, format!("mesh index {} out of range", i)));
+                }
+                let material = self.resolve_material(entry, material)?;
+                scene.objects.push(Object::TriangleMesh(TriangleMesh::new(positions, normals, uvs, indices, material)));
+            },
+            ObjectDesc::Plane { point, normal, material } => {
+                if normal == Vec3::ZERO {
+                    return Err(self.entry_error(entry, "plane normal must not be zero"));
+                }
+                let material = self.resolve_material(entry, material)?;
+                scene.objects.push(Object::Plane(Plane::new(point, normal, material)));
+            },
+            ObjectDesc::Quad { corner, u, v, material } => {
+                if u.cross(v) == Vec3::ZERO {
+                    return Err(self.entry_error(entry, "quad edges must not be parallel"));
+                }
+                let material = self.resolve_material(entry, material)?;
+                scene.objects.push(Object::Quad(Quad::new(corner, u, v, material)));
+            },
+            ObjectDesc::Disk { center, normal, radius, material } => {
+                if normal == Vec3::ZERO {
+                    return Err(self.entry_error(entry, "disk normal must not be zero"));
+                }
+                if !(radius > 0.0) {
+                    return Err(self.entry_error(entry, "disk radius must be positive"));
+                }
+                let material = self.resolve_material(entry, material)?;
+                scene.objects.push(Object::Disk(Disk::new(center, normal, radius, material)));
+            },
+            ObjectDesc::Box { min, max, material } => {
+                if !min.cmplt(max).all() {
+                    return Err(self.entry_error(entry, "box min must be less than max"));
+                }
+                let material = self.resolve_material(entry, material)?;
+                scene.objects.push(Object::AxisAlignedBox(AxisAlignedBox::new(min, max, material)));
+            },
+            ObjectDesc::Cylinder { center, radius, height, material } => {
+                if !(radius > 0.0) || !(height > 0.0) {
+                    return Err(self.entry_error(entry, "cylinder radius and height must be positive"));
+                }
+                let material = self.resolve_material(entry, material)?;
+                scene.objects.push(Object::Cylinder(Cylinder::new(center, radius, height, material)));
+            },
+            ObjectDesc::Obj { path, material } => {
+                let default_material = match material {
+                    Some(material) => self.resolve_material(entry, material)?,
+                    None => Material::Lambertian(Lambertian { albedo: Texture::Solid(Color::new(0.8, 0.8, 0.8)) }),
+                };
+                let objects = obj::load_obj(&self.resolve_path(&path), default_material).map_err(SceneFileError::Obj)?;
+                scene.objects.extend(objects);
+            },
+            ObjectDesc::ConstantMedium { mut boundary, density, material: material_value } => {
+                let material = self.resolve_medium(entry, density, material_value.clone())?;
+
+                let boundary_entry = format!("{}.boundary", entry);
+                if let serde_json::Value::Object(fields) = &mut boundary {
+                    fields.entry("material").or_insert(material_value);
+                }
+                let boundary = self.load_single_object(&boundary_entry, boundary)?;
+                scene.objects.push(Object::ConstantMedium(ConstantMedium::new(boundary, density, material)));
+            },
+            ObjectDesc::Instance { prototype, transform } => {
+                let index = *self.prototype_names.get(&prototype)
+                    .ok_or_else(|| self.entry_error(entry, format!("unknown prototype '{}'", prototype)))?;
+                let transform = transform.to_affine();
+                if transform.matrix3.determinant() == 0.0 {
+                    return Err(self.entry_error(entry, "instance transform must not scale by 0"));
+                }
+                scene.objects.push(Object::Instance(Instance::new(index, &self.prototypes[index], transform)));
+            },
+            ObjectDesc::Moving { object, translation, time0, time1 } => {
+                let object = self.load_single_object(&format!("{}.object", entry), object)?;
+                scene.objects.push(Object::Moving(Moving::new(object, translation, time0, time1)));
+            },
+        }
+        Ok(())
+    }
+
+    /// Load an object nested in another, which mustn't turn into several like an OBJ file can
+    fn load_single_object(&mut self, entry: &str, value: serde_json::Value) -> Result<Object, SceneFileError> {
+        let desc: ObjectDesc = serde_json::from_value(value).map_err(|e| self.entry_error(entry, e))?;
+        let mut objects = Scene::new();
+        self.load_object(entry, desc, &mut objects)?;
+        if objects.objects.len() != 1 {
+            return Err(self.entry_error(entry, "must be a single object"));
+        }
+        Ok(objects.objects.pop().unwrap())
+    }
+}
+
+/// Load a scene file
+pub fn load(path: &Path) -> Result<SceneDescription, SceneFileError> {
+    let src = std::fs::read_to_string(path).map_err(|e| SceneFileError::Io(path.to_owned(), e))?;
+    let file: SceneFile = serde_json::from_str(&src).map_err(|e| SceneFileError::Json(path.to_owned(), e))?;
+    if file.version != SCENE_FILE_VERSION {
+        return Err(SceneFileError::UnsupportedVersion(path.to_owned(), file.version));
+    }
+
+    let mut loader = Loader {
+        path,
+        textures: BTreeMap::new(),
+        materials: BTreeMap::new(),
+        images: Vec::new(),
+        prototypes: Vec::new(),
+        prototype_names: BTreeMap::new(),
+    };
+    // The server holds image sizes as u16, and pixel positions are divided by the size minus one
+    for (entry, size) in [("render.width", file.render.width), ("render.height", file.render.height)] {
+        if let Some(size) = size {
+            if size < 2 || u16::try_from(size).is_err() {
+                return Err(loader.entry_error(entry, format!("must be between 2 and {}", u16::MAX)));
+            }
+        }
+    }
+    if let Some(tonemap) = &file.render.tonemap {
+        if !(tonemap.gamma > 0.0) || !tonemap.exposure.is_finite() {
+            return Err(loader.entry_error("render.tonemap", "gamma must be above 0 and exposure finite"));
+        }
+    }
+    // Zero sigmas divide zero by zero in the filter weights
+    if let Some(denoiser) = &file.render.denoise {
+        let sigmas = [denoiser.sigma_spatial, denoiser.sigma_color, denoiser.sigma_normal, denoiser.sigma_albedo];
+        if denoiser.radius < 1 || !sigmas.iter().all(|&sigma| sigma > 0.0) {
+            return Err(loader.entry_error("render.denoise", "radius must be at least 1 and sigmas above 0"));
+        }
+    }
+    for (name, value) in file.textures {
+        let texture = loader.parse_texture(&format!("textures.{}", name), value)?;
+        loader.textures.insert(name, texture);
+    }
+    for (name, value) in file.materials {
+        let material = loader.parse_material(&format!("materials.{}", name), value)?;
+        loader.materials.insert(name, material);
+    }
+    // Prototypes can instance the ones before them in name order, so there can't be a cycle
+    for (name, value) in file.prototypes {
+        let prototype = loader.load_single_object(&format!("prototypes.{}", name), value)?;
+        loader.prototypes.push(prototype);
+        loader.prototype_names.insert(name, loader.prototypes.len() - 1);
+    }
+
+    let mut scene = Scene::new();
+    scene.environment = loader.load_environment(file.environment)?;
+    if let Some(FogDesc { density, material }) = file.fog {
+        let material = loader.resolve_medium("fog", density, material)?;
+        scene.fog = Some(Fog { density, material });
+    }
+    for (i, value) in file.objects.into_iter().enumerate() {
+        let entry = format!("objects[{}]", i);
+        let desc: ObjectDesc = serde_json::from_value(value).map_err(|e| loader.entry_error(&entry, e))?;
+        loader.load_object(&entry, desc, &mut scene)?;
+    }
+    scene.images = loader.images;
+    scene.prototypes = loader.prototypes;
+
+    Ok(SceneDescription {
+        scene,
+        camera: file.camera,
+        render: file.render,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load a scene file with the given camera and render settings and objects
+    fn load_scene(test: &str, render: &str, objects: &str) -> Result<SceneDescription, SceneFileError> {
+        let dir = std::env::temp_dir().join(format!("scenefile-test-{}-{}", std::process::id(), test));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scene.json");
+        let src = format!(
+            r#"{{ "version": 1, "camera": {{ "lookfrom": [0, 0, 5], "lookat": [0, 0, 0] }}, "render": {}, "objects": {} }}"#,
+            render, objects,
+        );
+        std::fs::write(&path, src).unwrap();
+        load(&path)
+    }
+
+    /// The entry and message of the error from loading a scene file
+    fn entry_error(test: &str, render: &str, objects: &str) -> (String, String) {
+        match load_scene(test, render, objects) {
+            Err(SceneFileError::Entry { entry, message, .. }) => (entry, message),
+            Err(e) => panic!("expected an entry error, got {}", e),
+            Ok(_) => panic!("expected an entry error"),
+        }
+    }
+
+    #[test]
+    fn example_scenes() {
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for name in ["example.json", "cornell.json"].iter() {
+            if let Err(e) = load(&scenes.join(name)) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn render_settings_errors() {
+        let (entry, message) = entry_error("width", r#"{ "width": 1 }"#, "[]");
+        assert_eq!(entry, "render.width");
+        assert_eq!(message, "must be between 2 and 65535");
+        let (entry, _) = entry_error("height", r#"{ "height": 70000 }"#, "[]");
+        assert_eq!(entry, "render.height");
+        let (entry, message) = entry_error("gamma", r#"{ "tonemap": { "gamma": 0 } }"#, "[]");
+        assert_eq!(entry, "render.tonemap");
+        assert_eq!(message, "gamma must be above 0 and exposure finite");
+        let (entry, message) = entry_error("sigma", r#"{ "denoise": { "sigma_color": 0 } }"#, "[]");
+        assert_eq!(entry, "render.denoise");
+        assert_eq!(message, "radius must be at least 1 and sigmas above 0");
+        let (entry, _) = entry_error("radius", r#"{ "denoise": { "radius": 0 } }"#, "[]");
+        assert_eq!(entry, "render.denoise");
+        assert!(load_scene("valid", r#"{ "width": 2, "height": 65535, "denoise": {} }"#, "[]").is_ok());
+    }
+
+    #[test]
+    fn object_errors() {
+        let sphere = r#"{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": { "type": "dielectric", "ir": 1.5 } }"#;
+        let (entry, message) = entry_error("sphere_radius", "{}", &format!(
+            r#"[{}, {{ "type": "sphere", "center": [0, 0, 0], "radius": 0, "material": "glass" }}]"#, sphere,
+        ));
+        assert_eq!(entry, "objects[1]");
+        assert_eq!(message, "sphere radius must be positive");
+        let (entry, message) = entry_error("material", "{}", r#"[{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "glass" }]"#);
+        assert_eq!(entry, "objects[0]");
+        assert_eq!(message, "unknown material 'glass'");
+        let (entry, message) = entry_error("inline", "{}", r#"[{ "type": "quad", "corner": [0, 0, 0], "u": [1, 0, 0], "v": [0, 1, 0],
+            "material": { "type": "lambertian", "albedo": "tiles" } }]"#);
+        assert_eq!(entry, "objects[0].material.albedo");
+        assert_eq!(message, "unknown texture 'tiles'");
+        let (entry, message) = entry_error("nested", "{}", r#"[{ "type": "moving", "translation": [0, 1, 0],
+            "object": { "type": "box", "min": [1, 0, 0], "max": [0, 1, 1], "material": "glass" } }]"#);
+        assert_eq!(entry, "objects[0].object");
+        assert_eq!(message, "box min must be less than max");
+        let (entry, message) = entry_error("type", "{}", r#"[{ "type": "teapot" }]"#);
+        assert_eq!(entry, "objects[0]");
+        assert!(message.contains("unknown variant `teapot`"), "{}", message);
+    }
+}
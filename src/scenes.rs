@@ -0,0 +1,458 @@
+//! Built in scenes, selectable by name, each with the camera and render settings which suit it
+
+use glam::{Affine3A, Quat};
+use rand::{Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::camera::{CameraSettings, ProjectionDesc};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Medium, Metal, MetallicRoughness};
+use crate::object::{AxisAlignedBox, ConstantMedium, Instance, Moving, Object, Plane, Quad, Sphere};
+use crate::scene::{Environment, Fog, Scene};
+use crate::scenefile::{RenderSettings, SceneDescription};
+use crate::shared::{Color, Point3, Vec3, color_random, color_random_range, degrees_to_radians};
+use crate::texture::{Checker, Noise, NoiseKind, Texture};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum BuiltinScene {
+    // Random spheres from the cover of Ray Tracing in One Weekend
+    #[serde(rename = "one-weekend")]
+    OneWeekend,
+    // Red and green walled box lit by a ceiling light
+    #[serde(rename = "cornell-box")]
+    CornellBox,
+    // Final scene of Ray Tracing: The Next Week, with media and motion blur
+    #[serde(rename = "next-week")]
+    NextWeek,
+    // White diffuse sphere under a uniform white sky, which should vanish into the background
+    #[serde(rename = "furnace")]
+    Furnace,
+    // Hundreds of small coloured lights over a floor, for light sampling
+    #[serde(rename = "many-lights")]
+    ManyLights,
+    // Glass shapes focusing a small light onto the floor
+    #[serde(rename = "glass-caustics")]
+    GlassCaustics,
+}
+
+impl BuiltinScene {
+    pub const ALL: [BuiltinScene; 6] = [
+        BuiltinScene::OneWeekend,
+        BuiltinScene::CornellBox,
+        BuiltinScene::NextWeek,
+        BuiltinScene::Furnace,
+        BuiltinScene::ManyLights,
+        BuiltinScene::GlassCaustics,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinScene::OneWeekend => "one-weekend",
+            BuiltinScene::CornellBox => "cornell-box",
+            BuiltinScene::NextWeek => "next-week",
+            BuiltinScene::Furnace => "furnace",
+            BuiltinScene::ManyLights => "many-lights",
+            BuiltinScene::GlassCaustics => "glass-caustics",
+        }
+    }
+
+    /// Build the scene along with its default camera and render settings
+    pub fn description(self) -> SceneDescription {
+        match self {
+            BuiltinScene::OneWeekend => SceneDescription {
+                scene: one_weekend_scene(),
+                camera: one_weekend_cam_settings(),
+                render: Default::default(),
+            },
+            BuiltinScene::CornellBox => cornell_box(),
+            BuiltinScene::NextWeek => next_week(),
+            BuiltinScene::Furnace => furnace(),
+            BuiltinScene::ManyLights => many_lights(),
+            BuiltinScene::GlassCaustics => glass_caustics(),
+        }
+    }
+}
+
+impl Default for BuiltinScene {
+    fn default() -> Self {
+        BuiltinScene::OneWeekend
+    }
+}
+
+impl fmt::Display for BuiltinScene {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BuiltinScene {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BuiltinScene::ALL.iter().copied().find(|scene| scene.name() == s).ok_or_else(|| {
+            let names: Vec<_> = BuiltinScene::ALL.iter().map(|scene| scene.name()).collect();
+            format!("unknown scene '{}', expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+fn lambertian(albedo: Color) -> Material {
+    Material::Lambertian(Lambertian { albedo: Texture::Solid(albedo) })
+}
+
+fn light(emit: Color) -> Material {
+    Material::DiffuseLight(DiffuseLight { emit })
+}
+
+/// Place a copy of prototype in the scene, rotated about Y by degrees and then translated
+fn add_instance(scene: &mut Scene, prototype: Object, rotate_y: f32, translate: Vec3) {
+    let transform = Affine3A::from_rotation_translation(Quat::from_rotation_y(degrees_to_radians(rotate_y)), translate);
+    scene.objects.push(Object::Instance(Instance::new(scene.prototypes.len(), &prototype, transform)));
+    scene.prototypes.push(prototype);
+}
+
+pub fn one_weekend_cam_settings() -> CameraSettings {
+    CameraSettings {
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        projection: ProjectionDesc::Perspective,
+        vfov: 20.0,
+        aperture: 0.1,
+        focus_dist: Some(10.0),
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    }
+}
+
+/// Generate the ray tracing in one weekend scene
+pub fn one_weekend_scene() -> Scene {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+    let mut scene = Scene::new();
+
+    let ground_material: Material = Material::Lambertian(Lambertian {
+        albedo: Texture::Solid(Color::new(0.5, 0.5, 0.5)),
+    });
+    scene.objects.push(Object::Plane(Plane::new(Point3::ZERO, Vec3::Y, ground_material)));
+
+    let mut spheres: Vec<(Point3, f32)> = Vec::new();
+    let mut add_sphere =
+        |spheres: &mut Vec<(Point3, f32)>, c: Point3, r: f32, mat: Material| {
+            scene.objects.push(Object::Sphere(Sphere::new(c, r, mat.clone())));
+            spheres.push((c, r));
+        };
+
+    let sphere_intersects = |spheres: &Vec<(Point3, f32)>, c: Point3, r: f32| {
+        spheres.iter().any(|s| (s.0 - c).length() < (s.1 + r))
+    };
+
+    let material1: Material = Material::Dielectric(Dielectric { ir: 1.5 });
+    add_sphere(&mut spheres, Point3::new(0.0, 1.0, 0.0), 1.0, material1);
+
+    let material2: Material = Material::Lambertian(Lambertian {
+        albedo: Texture::Solid(Color::new(0.4, 0.2, 0.1)),
+    });
+    add_sphere(&mut spheres, Point3::new(-4.0, 1.0, 0.0), 1.0, material2);
+
+    let material3: Material = Material::Metal(Metal {
+        albedo: Texture::Solid(Color::new(0.7, 0.6, 0.5)),
+        fuzz: 0.0,
+    });
+    add_sphere(&mut spheres, Point3::new(4.0, 1.0, 0.0), 1.0, material3);
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.gen_range(0.0..1.0);
+            let mut center;
+            // Find a position which doesn't intersect with any other sphere
+            loop {
+                center = Point3::new(
+                    a as f32 + 0.9 * rng.gen_range(0.0..1.0),
+                    0.2,
+                    b as f32 + 0.9 * rng.gen_range(0.0..1.0),
+                );
+                if !sphere_intersects(&spheres, center, 0.2) {
+                    break;
+                }
+            }
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.7 {
+                    // diffuse
+                    let albedo = color_random(&mut rng);
+                    let sphere_material: Material =
+                        Material::Lambertian(Lambertian { albedo: Texture::Solid(albedo) });
+                    add_sphere(&mut spheres, center, 0.2, sphere_material);
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = color_random_range(&mut rng, 0.5, 1.0);
+                    let fuzz = rng.gen_range(0.0..0.5);
+                    let sphere_material: Material = Material::Metal(Metal {
+                        albedo: Texture::Solid(albedo),
+                        fuzz: fuzz,
+                    });
+                    add_sphere(&mut spheres, center, 0.2, sphere_material);
+                } else {
+                    // glass
+                    let sphere_material: Material = Material::Dielectric(Dielectric { ir: 1.5 });
+                    add_sphere(&mut spheres, center, 0.2, sphere_material);
+                }
+            }
+        }
+    }
+
+    return scene;
+}
+
+/// The Cornell box at its usual 555 unit scale, with two rotated boxes
+fn cornell_box() -> SceneDescription {
+    let mut scene = Scene::new();
+    scene.environment = Environment::Solid(Color::ZERO);
+
+    let red = lambertian(Color::new(0.65, 0.05, 0.05));
+    let white = lambertian(Color::new(0.73, 0.73, 0.73));
+    let green = lambertian(Color::new(0.12, 0.45, 0.15));
+
+    let mut add_quad = |corner: Point3, u: Vec3, v: Vec3, material: Material| {
+        scene.objects.push(Object::Quad(Quad::new(corner, u, v, material)));
+    };
+    add_quad(Point3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0), green);
+    add_quad(Point3::ZERO, Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0), red);
+    add_quad(Point3::ZERO, Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0), white);
+    add_quad(Point3::splat(555.0), Vec3::new(-555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -555.0), white);
+    add_quad(Point3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), white);
+    // Facing down into the box, as lights only emit from their front
+    add_quad(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light(Color::splat(15.0)),
+    );
+
+    let tall_box = AxisAlignedBox::new(Point3::ZERO, Point3::new(165.0, 330.0, 165.0), white);
+    add_instance(&mut scene, Object::AxisAlignedBox(tall_box), 15.0, Vec3::new(265.0, 0.0, 295.0));
+    let short_box = AxisAlignedBox::new(Point3::ZERO, Point3::splat(165.0), white);
+    add_instance(&mut scene, Object::AxisAlignedBox(short_box), -18.0, Vec3::new(130.0, 0.0, 65.0));
+
+    SceneDescription {
+        scene,
+        camera: CameraSettings {
+            lookfrom: Point3::new(278.0, 278.0, -800.0),
+            lookat: Point3::new(278.0, 278.0, 0.0),
+            vup: Vec3::Y,
+            projection: ProjectionDesc::Perspective,
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        },
+        render: RenderSettings {
+            width: Some(400),
+            height: Some(400),
+            ..Default::default()
+        },
+    }
+}
+
+/// The final scene of Ray Tracing: The Next Week. The textured earth is replaced with marble, as
+/// built in scenes don't load images.
+fn next_week() -> SceneDescription {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+    let mut scene = Scene::new();
+    scene.environment = Environment::Solid(Color::ZERO);
+
+    // Floor of boxes of random heights
+    let ground = lambertian(Color::new(0.48, 0.83, 0.53));
+    let boxes_per_side = 20;
+    let w = 100.0;
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let min = Point3::new(-1000.0 + i as f32 * w, 0.0, -1000.0 + j as f32 * w);
+            let max = Point3::new(min.x + w, rng.gen_range(1.0..101.0), min.z + w);
+            scene.objects.push(Object::AxisAlignedBox(AxisAlignedBox::new(min, max, ground)));
+        }
+    }
+
+    scene.objects.push(Object::Quad(Quad::new(
+        Point3::new(423.0, 554.0, 412.0),
+        Vec3::new(-300.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -265.0),
+        light(Color::splat(7.0)),
+    )));
+
+    let center = Point3::new(400.0, 400.0, 200.0);
+    let moving = Sphere::new(center, 50.0, lambertian(Color::new(0.7, 0.3, 0.1)));
+    scene.objects.push(Object::Moving(Moving::new(Object::Sphere(moving), Vec3::new(30.0, 0.0, 0.0), 0.0, 1.0)));
+
+    let glass = Material::Dielectric(Dielectric { ir: 1.5 });
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::new(260.0, 150.0, 45.0), 50.0, glass)));
+    let metal = Material::Metal(Metal { albedo: Texture::Solid(Color::new(0.8, 0.8, 0.9)), fuzz: 1.0 });
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::new(0.0, 150.0, 145.0), 50.0, metal)));
+
+    // Blue subsurface sphere, a medium inside a glass shell
+    let shell = Sphere::new(Point3::new(360.0, 150.0, 145.0), 70.0, glass);
+    scene.objects.push(Object::Sphere(shell.clone()));
+    let blue = Material::Medium(Medium { albedo: Texture::Solid(Color::new(0.2, 0.4, 0.9)), anisotropy: 0.0 });
+    scene.objects.push(Object::ConstantMedium(ConstantMedium::new(Object::Sphere(shell), 0.2, blue)));
+
+    // Thin mist over everything
+    scene.fog = Some(Fog {
+        density: 0.0001,
+        material: Material::Medium(Medium { albedo: Texture::Solid(Color::ONE), anisotropy: 0.0 }),
+    });
+
+    let marble = Material::Lambertian(Lambertian {
+        albedo: Texture::Noise(Noise { kind: NoiseKind::Marble, color: Color::ONE, scale: 0.1, depth: 7 }),
+    });
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::new(400.0, 200.0, 400.0), 100.0, marble)));
+    let noise = Material::Lambertian(Lambertian {
+        albedo: Texture::Noise(Noise { kind: NoiseKind::Turbulence, color: Color::ONE, scale: 0.2, depth: 7 }),
+    });
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::new(220.0, 280.0, 300.0), 80.0, noise)));
+
+    // Rotated cube of small spheres
+    let white = lambertian(Color::splat(0.73));
+    let transform = Affine3A::from_rotation_translation(
+        Quat::from_rotation_y(degrees_to_radians(15.0)),
+        Vec3::new(-100.0, 270.0, 395.0),
+    );
+    for _ in 0..1000 {
+        let position = Point3::new(rng.gen_range(0.0..165.0), rng.gen_range(0.0..165.0), rng.gen_range(0.0..165.0));
+        let center = transform.transform_point3(position);
+        scene.objects.push(Object::Sphere(Sphere::new(center, 10.0, white)));
+    }
+
+    SceneDescription {
+        scene,
+        camera: CameraSettings {
+            lookfrom: Point3::new(478.0, 278.0, -600.0),
+            lookat: Point3::new(278.0, 278.0, 0.0),
+            vup: Vec3::Y,
+            projection: ProjectionDesc::Perspective,
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+        },
+        render: RenderSettings {
+            width: Some(400),
+            height: Some(400),
+            ..Default::default()
+        },
+    }
+}
+
+/// A white furnace test. The sphere reflects all light, so if no energy is lost or gained it can't
+/// be told apart from the sky behind it.
+fn furnace() -> SceneDescription {
+    let mut scene = Scene::new();
+    scene.environment = Environment::Solid(Color::ONE);
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::ZERO, 1.0, lambertian(Color::ONE))));
+
+    SceneDescription {
+        scene,
+        camera: CameraSettings {
+            lookfrom: Point3::new(0.0, 0.0, 5.0),
+            lookat: Point3::ZERO,
+            vup: Vec3::Y,
+            projection: ProjectionDesc::Perspective,
+            vfov: 30.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        },
+        render: RenderSettings {
+            // Deep paths are needed for all the light to come back out
+            max_depth: Some(200),
+            ..Default::default()
+        },
+    }
+}
+
+/// A grid of small coloured lights floating over a floor scattered with objects, under a black sky
+fn many_lights() -> SceneDescription {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+    let mut scene = Scene::new();
+    scene.environment = Environment::Solid(Color::ZERO);
+
+    let floor = Material::Lambertian(Lambertian {
+        albedo: Texture::Checker(Checker { even: Color::splat(0.2), odd: Color::splat(0.8), size: 1.0 }),
+    });
+    scene.objects.push(Object::Plane(Plane::new(Point3::ZERO, Vec3::Y, floor)));
+
+    for x in -12..12 {
+        for z in -12..12 {
+            let center = Point3::new(x as f32 + 0.5, rng.gen_range(1.5..3.0), z as f32 + 0.5);
+            let emit = 4.0 * color_random_range(&mut rng, 0.2, 1.0);
+            scene.objects.push(Object::Sphere(Sphere::new(center, 0.05, light(emit))));
+        }
+    }
+
+    for _ in 0..40 {
+        let center = Point3::new(rng.gen_range(-10.0..10.0), 0.5, rng.gen_range(-10.0..10.0));
+        let material = Material::MetallicRoughness(MetallicRoughness {
+            base_color: Texture::Solid(color_random(&mut rng)),
+            metallic: if rng.gen_bool(0.5) { 1.0 } else { 0.0 },
+            roughness: rng.gen_range(0.1..0.8),
+        });
+        scene.objects.push(Object::Sphere(Sphere::new(center, 0.5, material)));
+    }
+
+    SceneDescription {
+        scene,
+        camera: CameraSettings {
+            lookfrom: Point3::new(0.0, 6.0, 16.0),
+            lookat: Point3::new(0.0, 1.0, 0.0),
+            vup: Vec3::Y,
+            projection: ProjectionDesc::Perspective,
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        },
+        render: Default::default(),
+    }
+}
+
+/// Glass objects on a white floor lit by one small, bright light, so the light they focus shows up
+/// as caustics. Paths through glass to the light can only be found by chance, so this converges
+/// slowly and is a test of noise rather than something to render quickly.
+fn glass_caustics() -> SceneDescription {
+    let mut scene = Scene::new();
+    scene.environment = Environment::Solid(Color::splat(0.02));
+
+    scene.objects.push(Object::Plane(Plane::new(Point3::ZERO, Vec3::Y, lambertian(Color::splat(0.8)))));
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::new(3.0, 6.0, -2.0), 0.3, light(Color::splat(200.0)))));
+
+    let glass = Material::Dielectric(Dielectric { ir: 1.5 });
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, glass)));
+    let cube = AxisAlignedBox::new(Point3::splat(-0.6), Point3::splat(0.6), glass);
+    add_instance(&mut scene, Object::AxisAlignedBox(cube), 30.0, Vec3::new(-2.5, 0.6, 0.5));
+    let water = Material::Dielectric(Dielectric { ir: 1.33 });
+    scene.objects.push(Object::Sphere(Sphere::new(Point3::new(2.2, 0.5, 1.0), 0.5, water)));
+
+    SceneDescription {
+        scene,
+        camera: CameraSettings {
+            lookfrom: Point3::new(0.0, 4.0, 9.0),
+            lookat: Point3::new(0.0, 0.5, 0.0),
+            vup: Vec3::Y,
+            projection: ProjectionDesc::Perspective,
+            vfov: 35.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        },
+        render: RenderSettings {
+            samples_per_pixel: Some(512),
+            ..Default::default()
+        },
+    }
+}
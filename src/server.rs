@@ -6,37 +6,142 @@ use actix_web::middleware;
 use actix_web::web;
 use actix_web_actors::ws;
 use futures::prelude::*;
-use image::GenericImage;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::time::Duration;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::aov::Aov;
+use crate::camera::CameraSettings;
+use crate::denoise::Denoiser;
+use crate::filter::{self, Filter};
+use crate::hdr::{self, HdrImage};
 use crate::parallel::{self, ParallelExecutor};
-use crate::render;
+use crate::render::{self, RenderOutput};
+use crate::sampler::Sampler;
 use crate::scene::Scene;
+use crate::scenefile::{self, RenderSettings, SceneDescription};
+use crate::scenes::BuiltinScene;
 use crate::shared::Point3;
-use crate::{one_weekend_cam_lookat, one_weekend_scene};
+use crate::tonemap::{self, ToneMap};
 
 static INDEX_HTML: &[u8] = include_bytes!("../static/index.html");
 
 const THUMB_MAX_PX: u32 = 50;
 
-// Want to range from -5 to +5
-const PAN_RANGE: f32 = 10.;
+// Animations pan the lookat point over this fraction of its distance from the camera, so the
+// sweep is the same whatever the scene's scale (about -5 to +5 in the one weekend scene)
+const PAN_RANGE: f32 = 0.75;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 struct RenderJob {
+    scene: SceneChoice,
     total_frames: usize,
     samples_per_pixel: u32,
     width: u16,
     height: u16,
     parallel: ParallelType,
+    integrator: render::Integrator,
+    sampler: Sampler,
+    // Reconstruction filter, where a radius of 0 uses the filter's default
+    filter: filter::Kernel,
+    filter_radius: f32,
+    max_depth: u32,
+    roulette_depth: u32,
+    // Adaptive sampling is used when max_samples is above samples_per_pixel
+    max_samples: u32,
+    noise_threshold: f32,
+    // Frames are refined progressively in passes of this many samples per pixel, 0 renders each
+    // frame in a single pass
+    pass_samples: u32,
+    // Fraction of the time between frames over which the camera's pan is blurred, 0 for none.
+    // Moving objects are blurred over the scene camera's own shutter in every frame.
+    shutter: f32,
+    seed: u64,
+    // Tone mapping, which can be changed without re-rendering
+    tonemap: tonemap::Operator,
+    exposure: f32,
+    oetf: tonemap::Oetf,
+    gamma: f32,
+    // Image shown for each frame, which can also be changed without re-rendering
+    display: Layer,
+    // Denoise the beauty pass of each frame, also without re-rendering
+    denoise: bool,
 }
 
-#[derive(Clone)]
+/// The scene a job renders, either the scene file the server was started with or a built in scene.
+/// This is sent as "file" or the built in scene's name.
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+enum SceneChoice {
+    File,
+    Builtin(BuiltinScene),
+}
+
+impl TryFrom<String> for SceneChoice {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        match name.as_str() {
+            "file" => Ok(SceneChoice::File),
+            _ => name.parse().map(SceneChoice::Builtin),
+        }
+    }
+}
+
+impl From<SceneChoice> for String {
+    fn from(choice: SceneChoice) -> Self {
+        match choice {
+            SceneChoice::File => "file".to_owned(),
+            SceneChoice::Builtin(scene) => scene.name().to_owned(),
+        }
+    }
+}
+
+/// The scene being rendered, which jobs can switch
+struct CurrentScene {
+    choice: SceneChoice,
+    scene: Scene,
+    camera: CameraSettings,
+    // The scene file the server was started with, if any, to switch back to
+    file: Option<(Scene, CameraSettings, RenderSettings)>,
+}
+
+impl CurrentScene {
+    fn new(desc: SceneDescription, builtin: Option<BuiltinScene>) -> Self {
+        let SceneDescription { mut scene, camera, render } = desc;
+        scene.build_bvh();
+        let file = match builtin {
+            Some(_) => None,
+            None => Some((scene.clone(), camera.clone(), render)),
+        };
+        Self { choice: builtin.map_or(SceneChoice::File, SceneChoice::Builtin), scene, camera, file }
+    }
+
+    /// Switch to the chosen scene with its default camera, unless it's already current. Returns
+    /// the scene's render settings if it was switched.
+    fn select(&mut self, choice: SceneChoice) -> Option<RenderSettings> {
+        if choice == self.choice {
+            return None
+        }
+        let (scene, camera, render) = match choice {
+            SceneChoice::File => self.file.clone()?,
+            SceneChoice::Builtin(builtin) => {
+                let SceneDescription { mut scene, camera, render } = builtin.description();
+                scene.build_bvh();
+                (scene, camera, render)
+            },
+        };
+        *self = Self { choice, scene, camera, file: self.file.take() };
+        Some(render)
+    }
+}
+
+#[derive(Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 enum ParallelType {
     #[serde(rename = "per-block")]
@@ -45,36 +150,243 @@ enum ParallelType {
     PerFrame,
 }
 
-fn render_job_fields() -> serde_json::Value {
+/// One of the images rendered for each frame
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+enum Layer {
+    #[serde(rename = "beauty")]
+    Beauty,
+    #[serde(rename = "depth")]
+    Depth,
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "albedo")]
+    Albedo,
+    #[serde(rename = "object-id")]
+    ObjectId,
+    #[serde(rename = "sample-count")]
+    SampleCount,
+}
+
+impl Layer {
+    fn aov(self) -> Option<Aov> {
+        match self {
+            Layer::Beauty => None,
+            Layer::Depth => Some(Aov::Depth),
+            Layer::Normal => Some(Aov::Normal),
+            Layer::Albedo => Some(Aov::Albedo),
+            Layer::ObjectId => Some(Aov::ObjectId),
+            Layer::SampleCount => Some(Aov::SampleCount),
+        }
+    }
+
+    /// Convert for display, tone mapping the beauty pass and visualizing AOVs
+    fn to_ldr(self, img: &HdrImage, tonemap: &ToneMap) -> image::RgbImage {
+        match self.aov() {
+            None => tonemap.apply(img),
+            Some(aov) => aov.visualize(img),
+        }
+    }
+}
+
+/// Fields of a job for the control panel, where the scene file is only offered if there is one
+fn render_job_fields(scene_file: bool) -> serde_json::Value {
+    let mut scenes: Vec<_> = BuiltinScene::ALL.iter().map(|scene| scene.name()).collect();
+    if scene_file {
+        scenes.insert(0, "file");
+    }
     serde_json::json!([
+        ["scene", scenes],
         ["total_frames", "integer"],
         ["samples_per_pixel", "integer"],
         ["width", "integer"],
         ["height", "integer"],
         ["parallel", ["per-block", "per-frame"]],
+        ["integrator", ["next-event", "naive"]],
+        ["sampler", ["random", "stratified", "halton", "sobol", "blue-noise"]],
+        ["filter", ["box", "tent", "gaussian", "mitchell", "lanczos"]],
+        ["filter_radius", "float"],
+        ["max_depth", "integer"],
+        ["roulette_depth", "integer"],
+        ["max_samples", "integer"],
+        ["noise_threshold", "float"],
+        ["pass_samples", "integer"],
+        ["shutter", "float"],
+        ["seed", "integer"],
+        ["tonemap", ["clamp", "reinhard", "aces", "hable"]],
+        ["exposure", "float"],
+        ["oetf", ["gamma", "srgb"]],
+        ["gamma", "float"],
+        ["display", ["beauty", "depth", "normal", "albedo", "object-id", "sample-count"]],
+        ["denoise", "boolean"],
     ])
 }
 
 impl Default for RenderJob {
     fn default() -> Self {
         Self {
+            scene: SceneChoice::Builtin(BuiltinScene::default()),
             total_frames: 40,
             samples_per_pixel: 128/4,
             width: 1280/4,
             height: 720/4,
             parallel: ParallelType::PerFrame,
+            integrator: render::Integrator::NextEvent,
+            sampler: Sampler::default(),
+            filter: Filter::default().kernel,
+            filter_radius: 0.0,
+            max_depth: 50,
+            roulette_depth: 3,
+            max_samples: 0,
+            noise_threshold: scenefile::DEFAULT_NOISE_THRESHOLD,
+            pass_samples: 0,
+            shutter: 0.0,
+            seed: 0,
+            tonemap: ToneMap::default().operator,
+            exposure: ToneMap::default().exposure,
+            oetf: ToneMap::default().oetf,
+            gamma: ToneMap::default().gamma,
+            display: Layer::Beauty,
+            denoise: false,
         }
     }
 }
 
+impl RenderJob {
+    /// The default job, with any settings from a scene file applied
+    fn with_settings(settings: &RenderSettings) -> Self {
+        let default = Self::default();
+        let adaptive = settings.adaptive();
+        Self {
+            samples_per_pixel: settings.samples_per_pixel.unwrap_or(default.samples_per_pixel),
+            // Sizes are checked to fit when scene files are loaded
+            width: settings.width.map_or(default.width, |w| u16::try_from(w).unwrap_or(u16::MAX)),
+            height: settings.height.map_or(default.height, |h| u16::try_from(h).unwrap_or(u16::MAX)),
+            max_depth: settings.max_depth.unwrap_or(default.max_depth),
+            roulette_depth: settings.roulette_depth.unwrap_or(default.roulette_depth),
+            sampler: settings.sampler.unwrap_or(default.sampler),
+            filter: settings.filter.map_or(default.filter, |f| f.kernel),
+            filter_radius: settings.filter.and_then(|f| f.radius).unwrap_or(default.filter_radius),
+            max_samples: adaptive.map_or(default.max_samples, |a| a.max_samples),
+            noise_threshold: adaptive.map_or(default.noise_threshold, |a| a.noise_threshold),
+            pass_samples: settings.pass_samples.unwrap_or(default.pass_samples),
+            denoise: settings.denoise.is_some(),
+            ..default
+        }.with_tone_map(settings.tonemap.unwrap_or_default())
+    }
+
+    /// This job moved to a scene with its own settings, keeping the settings which aren't about
+    /// the scene such as the animation
+    fn with_scene_settings(self, settings: &RenderSettings) -> Self {
+        Self {
+            scene: self.scene,
+            total_frames: self.total_frames,
+            parallel: self.parallel,
+            integrator: self.integrator,
+            shutter: self.shutter,
+            seed: self.seed,
+            display: self.display,
+            ..Self::with_settings(settings)
+        }
+    }
+
+    /// Check the fields which the web page can set to values that can't be rendered
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.total_frames == 0 {
+            return Err("total_frames must be at least 1");
+        }
+        // Pixel positions are divided by the size minus one
+        if self.width < 2 || self.height < 2 {
+            return Err("width and height must be at least 2");
+        }
+        if self.samples_per_pixel == 0 {
+            return Err("samples_per_pixel must be at least 1");
+        }
+        if !(self.filter_radius >= 0.0) || !self.filter_radius.is_finite() {
+            return Err("filter_radius must be 0 or more");
+        }
+        if !(self.noise_threshold >= 0.0) || !self.noise_threshold.is_finite() {
+            return Err("noise_threshold must be 0 or more");
+        }
+        if !(self.shutter >= 0.0) || !self.shutter.is_finite() {
+            return Err("shutter must be 0 or more");
+        }
+        if !self.exposure.is_finite() {
+            return Err("exposure must be finite");
+        }
+        if !(self.gamma > 0.0) || !self.gamma.is_finite() {
+            return Err("gamma must be above 0");
+        }
+        Ok(())
+    }
+
+    fn tone_map(&self) -> ToneMap {
+        ToneMap { operator: self.tonemap, exposure: self.exposure, oetf: self.oetf, gamma: self.gamma }
+    }
+
+    fn with_tone_map(self, tonemap: ToneMap) -> Self {
+        Self {
+            tonemap: tonemap.operator,
+            exposure: tonemap.exposure,
+            oetf: tonemap.oetf,
+            gamma: tonemap.gamma,
+            ..self
+        }
+    }
+
+    /// Whether the frames for this job would be the same as for other, i.e. only the tone mapping,
+    /// displayed layer or denoising differ
+    fn same_frames(&self, other: &RenderJob) -> bool {
+        Self { display: other.display, denoise: other.denoise, ..self.clone().with_tone_map(other.tone_map()) } == *other
+    }
+}
+
 struct RenderFrame {
+    // Linear radiance and AOVs as rendered
+    output: RenderOutput,
+    // Denoised beauty pass, if the job asks for it
+    denoised: Option<HdrImage>,
+    // The displayed layer
     img: image::RgbImage,
     png: Vec<u8>,
 }
 
+impl RenderFrame {
+    fn new(output: RenderOutput, job: &RenderJob, denoiser: &Denoiser) -> Self {
+        Self::with_denoised(output, None, job, denoiser)
+    }
+
+    /// Display a frame for a job, keeping any existing denoised result
+    fn with_denoised(output: RenderOutput, denoised: Option<HdrImage>, job: &RenderJob, denoiser: &Denoiser) -> Self {
+        let denoised = if job.denoise {
+            Some(denoised.unwrap_or_else(|| denoiser.apply(&output)))
+        } else {
+            None
+        };
+        let mut frame = RenderFrame { output, denoised, img: image::RgbImage::new(0, 0), png: vec![] };
+
+        frame.img = match frame.image(job.display) {
+            Some(img) => job.display.to_ldr(img, &job.tone_map()),
+            None => image::RgbImage::new(frame.output.beauty.width(), frame.output.beauty.height()),
+        };
+        let thumb = image::DynamicImage::ImageRgb8(image::imageops::thumbnail(&frame.img, THUMB_MAX_PX, THUMB_MAX_PX));
+        thumb.write_to(&mut frame.png, image::ImageOutputFormat::Png).unwrap();
+        frame
+    }
+
+    fn image(&self, layer: Layer) -> Option<&HdrImage> {
+        match layer.aov() {
+            None => Some(self.denoised.as_ref().unwrap_or(&self.output.beauty)),
+            Some(aov) => self.output.aov(aov),
+        }
+    }
+}
+
 struct RenderStatus {
     job: RenderJob,
     frames: Vec<(usize, RenderFrame)>,
+    // Thumbnails of frames which are still being refined, in the order they arrived
+    previews: Vec<(usize, Vec<u8>)>,
     gif: Option<Vec<u8>>,
 }
 
@@ -83,6 +395,7 @@ impl Default for RenderStatus {
         Self {
             job: Default::default(),
             frames: vec![],
+            previews: vec![],
             gif: None,
         }
     }
@@ -93,15 +406,27 @@ enum ClientState {
     NeedsConfig,
     NeedsFrameMeta(usize),
     NeedsFrame(usize),
+    // Sending a preview while waiting for frame i
+    NeedsPreview(usize),
     NeedsGifMeta,
     NeedsGif,
     Complete,
 }
 
+struct Client {
+    state: ClientState,
+    // Number of previews which have been sent or skipped
+    previews_seen: usize,
+}
+
 struct MyServerDataInner {
-    clients: HashMap<Addr<MyWs>, ClientState>,
+    clients: HashMap<Addr<MyWs>, Client>,
     job_tx: crossbeam::channel::Sender<RenderJob>,
     render: RenderStatus,
+    // Settings used when a job asks for denoising
+    denoiser: Denoiser,
+    // Whether the server was started with a scene file, which jobs can choose
+    scene_file: bool,
 }
 
 #[derive(Clone)]
@@ -148,7 +473,7 @@ impl Handler<MyMsg> for MyWs {
             MyMsg::Meta(MetaMsg::Reset(job, pool_status)) =>
                 ctx.text(serde_json::json!({
                     "job": job,
-                    "job_fields": render_job_fields(),
+                    "job_fields": render_job_fields(self.state.lock().scene_file),
                     "pool_status": pool_status,
                 }).to_string()),
             MyMsg::Meta(MetaMsg::Frame { index }) =>
@@ -170,7 +495,7 @@ impl Actor for MyWs {
         println!("starting a websocket stream");
         let addr = ctx.address();
         // Stash away the current client in our master structure
-        let prev = self.state.lock().clients.insert(addr, ClientState::NeedsConfig);
+        let prev = self.state.lock().clients.insert(addr, Client { state: ClientState::NeedsConfig, previews_seen: 0 });
         assert!(prev.is_none())
     }
 
@@ -203,7 +528,16 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MyWs {
                         return
                     },
                 };
-                self.state.lock().job_tx.send(job).unwrap()
+                if let Err(e) = job.validate() {
+                    println!("ignoring invalid job: {}", e);
+                    return
+                }
+                let state = self.state.lock();
+                if job.scene == SceneChoice::File && !state.scene_file {
+                    println!("ignoring job for a scene file as the server wasn't started with one");
+                    return
+                }
+                state.job_tx.send(job).unwrap()
             },
             ws::Message::Close(_) => {
                 ctx.close(None);
@@ -220,13 +554,54 @@ async fn ws(state: ServerData, req: HttpRequest, stream: web::Payload) -> Result
     resp
 }
 
+/// Full size image of one layer of a frame, e.g. /frame/3/depth.pfm. The format is picked from
+/// the extension, with .pfm and .hdr keeping the linear values.
+async fn frame_image(state: ServerData, path: web::Path<(usize, String)>) -> HttpResponse {
+    let (index, file) = path.into_inner();
+    let (layer, extension) = match file.split_once('.') {
+        Some(parts) => parts,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let layer: Layer = match serde_json::from_value(serde_json::Value::String(layer.to_owned())) {
+        Ok(layer) => layer,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    // Encode without holding the lock, which would stall rendering
+    let (img, tone_map) = {
+        let state = state.lock();
+        let img = match state.render.frames.iter().find(|(idx, _)| *idx == index).and_then(|(_, frame)| frame.image(layer)) {
+            Some(img) => img.clone(),
+            None => return HttpResponse::NotFound().finish(),
+        };
+        (img, state.render.job.tone_map())
+    };
+    let mut data = vec![];
+    match extension {
+        "pfm" => hdr::write_pfm(&mut data, &img).unwrap(),
+        "hdr" => hdr::write_radiance(&mut data, &img).unwrap(),
+        "png" => {
+            let ldr = image::DynamicImage::ImageRgb8(layer.to_ldr(&img, &tone_map));
+            ldr.write_to(&mut data, image::ImageOutputFormat::Png).unwrap();
+        },
+        _ => return HttpResponse::NotFound().finish(),
+    }
+    let content_type = if extension == "png" { "image/png" } else { "application/octet-stream" };
+    HttpResponse::Ok().content_type(content_type).body(data)
+}
+
 async fn index() -> HttpResponse {
     HttpResponse::Ok().set(ContentType::html()).encoding(ContentEncoding::Gzip).body(INDEX_HTML)
 }
 
-pub fn main(addr: String, cpus: usize) {
+/// Serve renders of desc, which is the named built in scene or else came from a scene file
+pub fn main(addr: String, cpus: usize, desc: SceneDescription, builtin: Option<BuiltinScene>) {
     let (job_tx, job_rx) = crossbeam::channel::unbounded();
-    job_tx.send(Default::default()).unwrap(); // Reset to a valid job
+    let job = RenderJob {
+        scene: builtin.map_or(SceneChoice::File, SceneChoice::Builtin),
+        ..RenderJob::with_settings(&desc.render)
+    };
+    job_tx.send(job).unwrap(); // Reset to a valid job
 
     let state = MyServerData {
         inner: Arc::new(Mutex::new(
@@ -234,6 +609,8 @@ pub fn main(addr: String, cpus: usize) {
                 clients: HashMap::new(),
                 job_tx,
                 render: Default::default(),
+                denoiser: desc.render.denoise.unwrap_or_default(),
+                scene_file: builtin.is_none(),
             }
         ))
     };
@@ -245,6 +622,7 @@ pub fn main(addr: String, cpus: usize) {
         let app = app.wrap(middleware::Logger::default());
         let app = app.wrap(middleware::Compress::new(ContentEncoding::Auto));
         let app = app.route("/ws", web::get().to(ws));
+        let app = app.route("/frame/{index}/{file}", web::get().to(frame_image));
         let app = app.route("/", web::get().to(index));
         app
     };
@@ -258,8 +636,7 @@ pub fn main(addr: String, cpus: usize) {
     crossbeam::scope(move |scope| {
         scope.spawn(move |scope| {
 
-            let mut scene = one_weekend_scene();
-            scene.build_bvh();
+            let mut current = CurrentScene::new(desc, builtin);
 
             let mut frame_rx = None;
             let never = crossbeam::channel::never();
@@ -274,7 +651,7 @@ pub fn main(addr: String, cpus: usize) {
                 loop {
                     match job_rx.try_recv() {
                         Ok(job) => {
-                            frame_rx = Some(reset_job(job, &scene, &mut thread_state.lock(), scope, pool));
+                            start_job(job, &mut current, &thread_state, &mut frame_rx, scope, pool);
                         },
                         Err(crossbeam::channel::TryRecvError::Empty) => break,
                         Err(crossbeam::channel::TryRecvError::Disconnected) => {
@@ -289,7 +666,7 @@ pub fn main(addr: String, cpus: usize) {
                     recv(job_rx) -> msg => {
                         match msg {
                             Ok(job) => {
-                                frame_rx = Some(reset_job(job, &scene, &mut thread_state.lock(), scope, pool));
+                                start_job(job, &mut current, &thread_state, &mut frame_rx, scope, pool);
                             },
                             Err(crossbeam::channel::RecvError) => {
                                 println!("ERROR channel for receiving jobs closed");
@@ -300,15 +677,19 @@ pub fn main(addr: String, cpus: usize) {
                     // New frame arrived, process it
                     recv(frame_rx.as_ref().unwrap_or(&never)) -> msg => {
                         match msg {
-                            Ok((idx, w, h, raw)) => {
-                                let img = image::RgbImage::from_raw(w, h, raw).unwrap();
-
-                                let mut png = vec![];
-                                let thumb = image::DynamicImage::ImageRgb8(image::imageops::thumbnail(&img, THUMB_MAX_PX, THUMB_MAX_PX));
-                                thumb.write_to(&mut png, image::ImageOutputFormat::Png).unwrap();
+                            Ok((idx, output, finished)) => {
+                                // Frames arrive as linear radiance, tone map them for display
+                                let (job, denoiser) = thread_state.with(|s| (s.render.job.clone(), s.denoiser));
+                                // Previews are soon replaced by the next pass, so aren't worth denoising
+                                let job = RenderJob { denoise: job.denoise && finished, ..job };
+                                let frame = RenderFrame::new(output, &job, &denoiser);
                                 println!("finished creating a png");
 
-                                thread_state.lock().render.frames.push((idx, RenderFrame { img, png }));
+                                if finished {
+                                    thread_state.lock().render.frames.push((idx, frame));
+                                } else {
+                                    thread_state.lock().render.previews.push((idx, frame.png));
+                                }
                             },
                             Err(crossbeam::channel::RecvError) => {
                                 println!("finished receiving frames");
@@ -353,39 +734,80 @@ pub fn main(addr: String, cpus: usize) {
     }).unwrap();
 }
 
-fn reset_job<'a, 'b>(job: RenderJob, scene: &Scene, state: &mut MyServerDataInner, scope: &crossbeam::thread::Scope<'a>, pool: &'a impl ParallelExecutor) -> crossbeam::channel::Receiver<(usize, u32, u32, Vec<u8>)> {
+/// Start rendering a new job, unless only the tone mapping has changed in which case the existing
+/// frames are tone mapped again
+fn start_job<'a>(job: RenderJob, current: &mut CurrentScene, state: &MyServerData, frame_rx: &mut Option<crossbeam::channel::Receiver<(usize, RenderOutput, bool)>>, scope: &crossbeam::thread::Scope<'a>, pool: &'a impl ParallelExecutor) {
+    let started = frame_rx.is_some() || !state.lock().render.frames.is_empty();
+    if started && job.same_frames(&state.lock().render.job) {
+        redisplay_job(job, state);
+    } else {
+        let job = match current.select(job.scene) {
+            Some(settings) => job.with_scene_settings(&settings),
+            None => job,
+        };
+        *frame_rx = Some(reset_job(job, &current.scene, &current.camera, &mut state.lock(), scope, pool));
+    }
+}
+
+/// Redisplay the frames for a changed job. Denoising them can take a while, so it happens on
+/// copies of the frames without holding the lock. Only this thread adds frames, so none are missed
+/// in the meantime.
+fn redisplay_job(job: RenderJob, state: &MyServerData) {
+    let (frames, denoiser) = state.with(|s| {
+        let frames: Vec<_> = s.render.frames.iter().map(|(idx, frame)| (*idx, frame.output.clone(), frame.denoised.clone())).collect();
+        (frames, s.denoiser)
+    });
+    let frames = frames.into_iter().map(|(idx, output, denoised)| (idx, RenderFrame::with_denoised(output, denoised, &job, &denoiser))).collect();
+
+    let mut state = state.lock();
+    state.render.frames = frames;
+    state.render.job = job;
+    // Previews are only kept for the display they were made with
+    state.render.previews.clear();
+    // The gif is recreated once all frames are present
+    state.render.gif = None;
+    // Reset clients to receive the new job config and frames
+    for (_, client) in state.clients.iter_mut() {
+        client.state = ClientState::NeedsConfig
+    }
+}
+
+fn reset_job<'a, 'b>(job: RenderJob, scene: &Scene, camera: &CameraSettings, state: &mut MyServerDataInner, scope: &crossbeam::thread::Scope<'a>, pool: &'a impl ParallelExecutor) -> crossbeam::channel::Receiver<(usize, RenderOutput, bool)> {
     let (frame_tx, frame_rx) = crossbeam::channel::unbounded();
     let scene = scene.clone();
+    let camera = camera.clone();
     match job.parallel {
         ParallelType::PerBlock => {
             let job = job.clone();
             scope.spawn(move |_| {
                 for idx in 0..job.total_frames {
-                    let render_worker = make_renderer(idx, scene.clone(), job.clone());
-                    let img = futures::executor::block_on(render_frame_parallel(render_worker, pool));
-                    println!("finished rendering a frame");
-                    match frame_tx.send((idx, img.width(), img.height(), img.into_raw())) {
-                        Ok(()) => (),
-                        Err(crossbeam::channel::SendError(_)) => {
-                            println!("terminating a processing thread as frame channel has closed");
-                            return
-                        },
+                    let render_worker = make_renderer(idx, scene.clone(), &camera, job.clone());
+                    let passes = render_frame_parallel(render_worker, pool);
+                    futures::pin_mut!(passes);
+                    while let Some((output, finished)) = futures::executor::block_on(passes.next()) {
+                        match frame_tx.send((idx, output, finished)) {
+                            Ok(()) => (),
+                            Err(crossbeam::channel::SendError(_)) => {
+                                println!("terminating a processing thread as frame channel has closed");
+                                return
+                            },
+                        }
                     }
+                    println!("finished rendering a frame");
                 }
             });
         },
         ParallelType::PerFrame => {
             let job = job.clone();
             scope.spawn(move |_| {
-                let mut futs: futures::stream::FuturesUnordered<_> = (0..job.total_frames)
+                let mut passes = futures::stream::select_all((0..job.total_frames)
                     .map(|idx| {
-                        let render_worker = make_renderer(idx, scene.clone(), job.clone());
-                        render_frame(render_worker, pool).map(move |img| (idx, img))
-                    })
-                    .collect();
+                        let render_worker = make_renderer(idx, scene.clone(), &camera, job.clone());
+                        render_frame(render_worker, pool).map(move |(output, finished)| (idx, output, finished)).boxed_local()
+                    }));
                 futures::executor::block_on(async {
-                    while let Some((idx, img)) = futs.next().await {
-                        match frame_tx.send((idx, img.width(), img.height(), img.into_raw())) {
+                    while let Some((idx, output, finished)) = passes.next().await {
+                        match frame_tx.send((idx, output, finished)) {
                             Ok(()) => (),
                             Err(crossbeam::channel::SendError(_)) => {
                                 println!("terminating a processing thread as frame channel has closed");
@@ -397,31 +819,87 @@ fn reset_job<'a, 'b>(job: RenderJob, scene: &Scene, state: &mut MyServerDataInne
             });
         },
     }
-    state.render = RenderStatus { job, frames: vec![], gif: None };
+    state.render = RenderStatus { job, frames: vec![], previews: vec![], gif: None };
     // Reset clients to receive the new job config
-    for (_, cs) in state.clients.iter_mut() {
-        *cs = ClientState::NeedsConfig
+    for (_, client) in state.clients.iter_mut() {
+        client.state = ClientState::NeedsConfig
     }
     frame_rx
 }
 
-fn make_renderer(idx: usize, scene: Scene, job: RenderJob) -> render::Renderer {
-    let delta_increment = PAN_RANGE / job.total_frames as f32;
-    let delta_mult = (-(job.total_frames as f32) * delta_increment / 2.) + (idx as f32 * delta_increment);
-    let cam = one_weekend_cam_lookat(job.width.into(), job.height.into(), Point3::ZERO + (Point3::ONE * delta_mult));
-    render::Renderer::new(job.width.into(), job.height.into(), job.samples_per_pixel, scene, cam)
+fn make_renderer(idx: usize, scene: Scene, camera: &CameraSettings, job: RenderJob) -> render::Renderer {
+    // The camera pans from frame to frame, while the scene's shutter times are left alone so
+    // moving objects are in the same place in every frame
+    let delta_increment = PAN_RANGE * (camera.lookfrom - camera.lookat).length() / job.total_frames as f32;
+    let camera_at = |frame: f32| {
+        let delta_mult = (-(job.total_frames as f32) * delta_increment / 2.) + (frame * delta_increment);
+        let mut camera = camera.clone();
+        camera.lookat += Point3::ONE * delta_mult;
+        camera
+    };
+    let (width, height) = (job.width.into(), job.height.into());
+    let cam = if job.shutter > 0.0 {
+        camera_at(idx as f32).build_moving(&camera_at(idx as f32 + job.shutter), width, height)
+    } else {
+        camera_at(idx as f32).build(width, height)
+    };
+    let mut renderer = render::Renderer::new(job.width.into(), job.height.into(), job.samples_per_pixel, scene, cam);
+    renderer.set_integrator(job.integrator);
+    renderer.set_sampler(job.sampler);
+    renderer.set_filter(Filter {
+        kernel: job.filter,
+        radius: if job.filter_radius > 0.0 { Some(job.filter_radius) } else { None },
+    });
+    renderer.set_max_depth(job.max_depth);
+    renderer.set_roulette_depth(job.roulette_depth);
+    renderer.set_seed(job.seed, idx as u32);
+    if job.max_samples > job.samples_per_pixel {
+        renderer.set_adaptive(Some(render::AdaptiveSampling {
+            max_samples: job.max_samples,
+            noise_threshold: job.noise_threshold,
+        }));
+    }
+    if job.pass_samples > 0 {
+        renderer.set_progressive(Some(job.pass_samples));
+    }
+    // All AOVs are rendered so the displayed layer can be switched afterwards
+    renderer.set_aovs(&Aov::ALL);
+    renderer
 }
 
-fn render_frame(render_worker: render::Renderer, pool: &impl ParallelExecutor) -> impl Future<Output=image::RgbImage> {
+/// Render a frame, streaming the result after each pass along with whether it's the final one
+fn render_frame<'a>(render_worker: render::Renderer, pool: &'a impl ParallelExecutor) -> impl Stream<Item=(RenderOutput, bool)> + 'a {
+    let pass_count = render_worker.pass_count();
     render_worker.render_frame_single(pool)
+        .enumerate()
+        .map(move |(pass, output)| (output, pass as u32 + 1 == pass_count))
 }
 
-fn render_frame_parallel(render_worker: render::Renderer, pool: &impl ParallelExecutor) -> impl Future<Output=image::RgbImage> {
-    let img = image::RgbImage::new(render_worker.width(), render_worker.height());
-    render_worker.render_frame_parallel(pool).fold(img, |mut img, (renderblock, result_img)| {
-        img.copy_from(&result_img, renderblock.x, renderblock.y).unwrap();
-        future::ready(img)
-    })
+/// Render a frame in blocks, streaming the assembled result after each pass like `render_frame`
+fn render_frame_parallel<'a>(render_worker: render::Renderer, pool: &'a impl ParallelExecutor) -> impl Stream<Item=(RenderOutput, bool)> + 'a {
+    let output = RenderOutput::new(render_worker.width(), render_worker.height(), &Aov::ALL);
+    render_worker.render_frame_parallel(pool)
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .scan((output, 0), |(output, pass), block| {
+            let result = match block {
+                Some((renderblock, result)) => {
+                    // Every block of a pass arrives before any of the next, so a new pass means
+                    // the previous one is complete
+                    let preview = if renderblock.pass != *pass {
+                        *pass = renderblock.pass;
+                        Some((output.clone(), false))
+                    } else {
+                        None
+                    };
+                    output.copy_from(&result, renderblock.x, renderblock.y);
+                    preview
+                },
+                None => Some((std::mem::replace(output, RenderOutput::new(0, 0, &[])), true)),
+            };
+            future::ready(Some(result))
+        })
+        .filter_map(future::ready)
 }
 
 fn render_gif(state: &mut MyServerDataInner) {
@@ -438,25 +916,44 @@ fn render_gif(state: &mut MyServerDataInner) {
 }
 
 fn update_clients(state: &mut MyServerDataInner, pool_status: String) {
-    for (addr, cs) in state.clients.iter_mut() {
-        update_client(addr, cs, &state.render, &pool_status);
+    for (addr, client) in state.clients.iter_mut() {
+        update_client(addr, client, &state.render, &pool_status);
     }
 }
 
-fn update_client(addr: &Addr<MyWs>, cs: &mut ClientState, render: &RenderStatus, pool_status: &str) {
+fn update_client(addr: &Addr<MyWs>, client: &mut Client, render: &RenderStatus, pool_status: &str) {
     loop {
-        let (msg, next_cs) = match *cs {
+        let (msg, next_cs) = match client.state {
             // Send the config
-            ClientState::NeedsConfig => (MyMsg::Meta(MetaMsg::Reset(render.job.clone(), pool_status.to_owned())), ClientState::NeedsFrameMeta(0)),
+            ClientState::NeedsConfig => {
+                client.previews_seen = 0;
+                (MyMsg::Meta(MetaMsg::Reset(render.job.clone(), pool_status.to_owned())), ClientState::NeedsFrameMeta(0))
+            },
             // Wants more frames, but the frames are finished - move onto the gif
             ClientState::NeedsFrameMeta(i) if i == render.job.total_frames => {
-                *cs = ClientState::NeedsGifMeta;
+                client.state = ClientState::NeedsGifMeta;
                 continue
             },
-            // Wants more frames, but nothing to send yet
-            ClientState::NeedsFrameMeta(i) if i == render.frames.len() => break,
+            // Wants more frames, but no finished frame to send yet - send the latest preview of
+            // any unfinished frame in the meantime
+            ClientState::NeedsFrameMeta(i) if i == render.frames.len() => {
+                let previews = &render.previews;
+                let is_stale = |p: usize| {
+                    let idx = previews[p].0;
+                    render.frames.iter().any(|(f, _)| *f == idx) || previews[p+1..].iter().any(|(f, _)| *f == idx)
+                };
+                while client.previews_seen < previews.len() && is_stale(client.previews_seen) {
+                    client.previews_seen += 1;
+                }
+                match previews.get(client.previews_seen) {
+                    Some((idx, _)) => (MyMsg::Meta(MetaMsg::Frame { index: *idx }), ClientState::NeedsPreview(i)),
+                    None => break,
+                }
+            },
             // Send a frame
             ClientState::NeedsFrame(i) => (MyMsg::Binary(render.frames[i].1.png.clone()), ClientState::NeedsFrameMeta(i+1)),
+            // Send a preview, then go back to waiting for the frame
+            ClientState::NeedsPreview(i) => (MyMsg::Binary(render.previews[client.previews_seen].1.clone()), ClientState::NeedsFrameMeta(i)),
             ClientState::NeedsGif => {
                 match render.gif.as_ref() {
                     // Send the gif
@@ -473,7 +970,12 @@ fn update_client(addr: &Addr<MyWs>, cs: &mut ClientState, render: &RenderStatus,
         };
         // If the send was sccessful, increment the progress for this client
         match addr.try_send(msg) {
-            Ok(()) => *cs = next_cs,
+            Ok(()) => {
+                if let ClientState::NeedsPreview(_) = client.state {
+                    client.previews_seen += 1;
+                }
+                client.state = next_cs
+            },
             Err(actix::prelude::SendError::Full(_)) => {
                 println!("failed to send to full mailbox");
                 break
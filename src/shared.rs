@@ -1,7 +1,7 @@
 pub use bvh::aabb::{Bounded, AABB};
 pub use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
-pub use glam::Vec3;
-use rand::Rng;
+pub use glam::{Vec2, Vec3};
+use rand::{Rng, SeedableRng};
 
 pub type Point3 = glam::Vec3;
 pub type Color = glam::Vec3;
@@ -9,21 +9,28 @@ pub type Color = glam::Vec3;
 pub const TRACE_EPSILON: f32 = 0.001;
 pub const TRACE_INFINITY: f32 = f32::MAX;
 
-/// A minimal ray
+/// A minimal ray, at a time while the camera's shutter is open
 #[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    pub time: f32,
 }
 
 impl Ray {
-    pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+    pub fn new(origin: Vec3, direction: Vec3, time: f32) -> Self {
+        Ray { origin, direction, time }
     }
 
     pub fn at(&self, t: f32) -> Point3 {
         self.origin + t * self.direction
     }
+
+    /// Convert to a ray for BVH traversal. The bvh crate takes the sign of -0.0 as positive but
+    /// inverts it to -inf, which misses every box, so negative zeros are made positive first.
+    pub fn to_bvh(self) -> bvh::ray::Ray {
+        bvh::ray::Ray::new(self.origin, self.direction + Vec3::ZERO)
+    }
 }
 
 /// A RayQuery for intersection
@@ -34,19 +41,37 @@ pub struct RayQuery {
     pub t_max: f32,
 }
 
-pub fn rgb_from_render(c: Color) -> image::Rgb<u8> {
-    let gamma = 1.0 / 2.2;
-    let col_gamma = Color::new(c.x.powf(gamma), c.y.powf(gamma), c.z.powf(gamma));
-    let v = [(255.0 * col_gamma.x) as u8, (255.0 * col_gamma.y) as u8, (255.0 * col_gamma.z) as u8];
-    image::Rgb(v)
+/// Random number generator used for rendering
+pub type SampleRng = rand_pcg::Pcg32;
+
+/// SplitMix64 finalizer, which scrambles the bits of x
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Random number stream for one sample of one pixel. This depends only on its arguments, so a
+/// pixel renders identically wherever and in whatever order it is rendered.
+pub fn sample_rng(seed: u64, frame: u32, x: u32, y: u32, sample: u32) -> SampleRng {
+    let mut key = splitmix64(seed);
+    for v in &[frame, x, y, sample] {
+        key = splitmix64(key ^ *v as u64);
+    }
+    SampleRng::seed_from_u64(key)
+}
+
+/// Relative luminance of a linear Rec. 709 colour
+pub fn luminance(c: Color) -> f32 {
+    c.dot(Color::new(0.2126, 0.7152, 0.0722))
 }
 
 pub fn degrees_to_radians(degrees: f32) -> f32 {
     return degrees * std::f32::consts::PI / 180.0;
 }
 
-pub fn vec3_random_range(min: f32, max: f32) -> Vec3 {
-    let mut rng = rand::thread_rng();
+pub fn vec3_random_range<T: Rng>(rng: &mut T, min: f32, max: f32) -> Vec3 {
     return Vec3::new(
         rng.gen_range(min..max),
         rng.gen_range(min..max),
@@ -54,22 +79,22 @@ pub fn vec3_random_range(min: f32, max: f32) -> Vec3 {
     );
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
+pub fn random_in_unit_sphere<T: Rng>(rng: &mut T) -> Vec3 {
     loop {
-        let p = vec3_random_range(-1.0, 1.0);
+        let p = vec3_random_range(rng, -1.0, 1.0);
         if p.length_squared() < 1.0 {
             return p;
         }
     }
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    return random_in_unit_sphere().normalize();
+pub fn random_unit_vector<T: Rng>(rng: &mut T) -> Vec3 {
+    return random_in_unit_sphere(rng).normalize();
 }
 
 #[allow(dead_code)]
-pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
+pub fn random_in_hemisphere<T: Rng>(rng: &mut T, normal: Vec3) -> Vec3 {
+    let in_unit_sphere = random_in_unit_sphere(rng);
     if in_unit_sphere.dot(normal) > 0.0 {
         return in_unit_sphere; // In the same hemisphere as the normal
     } else {
@@ -77,6 +102,14 @@ pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
     }
 }
 
+/// Orthonormal basis (u, v, w) where w is the given unit vector
+pub fn orthonormal_basis(w: Vec3) -> (Vec3, Vec3, Vec3) {
+    let a = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let v = w.cross(a).normalize();
+    let u = w.cross(v);
+    (u, v, w)
+}
+
 /// Vec3 extensions
 pub trait VecExt {
     fn near_zero(&self) -> bool;
@@ -112,14 +145,20 @@ pub fn ceil_div(x: u32, y: u32) -> u32 {
     (x + y - 1) / y
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
-    loop {
-        let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+/// Map a point in [0, 1)^2 onto the unit disk in the xy plane, using Shirley and Chiu's concentric
+/// mapping which keeps any stratification of the input
+pub fn sample_unit_disk(u: Vec2) -> Vec3 {
+    let offset = 2.0 * u - Vec2::ONE;
+    if offset.x == 0.0 && offset.y == 0.0 {
+        return Vec3::ZERO;
     }
+    let quarter_pi = std::f32::consts::FRAC_PI_4;
+    let (r, theta) = if offset.x.abs() > offset.y.abs() {
+        (offset.x, quarter_pi * (offset.y / offset.x))
+    } else {
+        (offset.y, 2.0 * quarter_pi - quarter_pi * (offset.x / offset.y))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 pub fn color_random<T: Rng>(rng: &mut T) -> Color {
@@ -0,0 +1,255 @@
+use lazy_static::lazy_static;
+use rand::{Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+
+use crate::hdr::HdrImage;
+use crate::object::HitRecord;
+use crate::shared::{Color, Point3, SampleRng, Vec3, random_unit_vector};
+
+// Size of the Perlin noise lattice before it repeats
+const PERLIN_POINT_COUNT: usize = 256;
+
+/// Colour which varies over a surface, looked up by the UV or position of a hit. Images are too
+/// large to copy around with materials, so they're kept in the scene and referenced by index.
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub enum Texture {
+    Solid(Color),
+    Checker(Checker),
+    Noise(Noise),
+    Image(ImageRef),
+}
+
+impl Texture {
+    /// Colour at a hit, with images from the scene's image table
+    pub fn value(&self, hit: &HitRecord, images: &[ImageTexture]) -> Color {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker(checker) => checker.value(hit.point),
+            Texture::Noise(noise) => noise.value(hit.point),
+            Texture::Image(image) => images[image.index].value(hit.uv.x, hit.uv.y, image.wrap),
+        }
+    }
+}
+
+/// Checkerboard of cubes in 3D space, so it needs no UVs
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Checker {
+    pub even: Color,
+    pub odd: Color,
+    // Width of each cube
+    pub size: f32,
+}
+
+impl Checker {
+    fn value(&self, p: Point3) -> Color {
+        let cell = (p / self.size).floor();
+        if (cell.x + cell.y + cell.z).rem_euclid(2.0) < 1.0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Pattern made from Perlin noise
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum NoiseKind {
+    // Smooth noise
+    #[serde(rename = "perlin")]
+    Perlin,
+    // Sum of octaves of noise
+    #[serde(rename = "turbulence")]
+    Turbulence,
+    // Stripes along Z distorted by turbulence
+    #[serde(rename = "marble")]
+    Marble,
+}
+
+/// Colour scaled by a noise pattern in 3D space
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    pub color: Color,
+    // Frequency of the noise
+    pub scale: f32,
+    // Octaves of turbulence
+    pub depth: u32,
+}
+
+impl Noise {
+    fn value(&self, p: Point3) -> Color {
+        let perlin = perlin();
+        let p = self.scale * p;
+        let v = match self.kind {
+            NoiseKind::Perlin => 0.5 * (1.0 + perlin.noise(p)),
+            NoiseKind::Turbulence => perlin.turbulence(p, self.depth),
+            NoiseKind::Marble => 0.5 * (1.0 + (p.z + 10.0 * perlin.turbulence(p, self.depth)).sin()),
+        };
+        self.color * v
+    }
+}
+
+/// Random gradients on a repeating lattice, generated from a fixed seed so every worker has the same
+struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+lazy_static! {
+    static ref PERLIN: Perlin = Perlin::new();
+}
+
+fn perlin() -> &'static Perlin {
+    &PERLIN
+}
+
+impl Perlin {
+    fn new() -> Self {
+        let mut rng = SampleRng::seed_from_u64(0);
+        let gradients = (0..PERLIN_POINT_COUNT).map(|_| random_unit_vector(&mut rng)).collect();
+        let permutation = |rng: &mut SampleRng| {
+            let mut perm: Vec<usize> = (0..PERLIN_POINT_COUNT).collect();
+            for i in (1..PERLIN_POINT_COUNT).rev() {
+                perm.swap(i, rng.gen_range(0..=i));
+            }
+            perm
+        };
+        Perlin {
+            perm_x: permutation(&mut rng),
+            perm_y: permutation(&mut rng),
+            perm_z: permutation(&mut rng),
+            gradients,
+        }
+    }
+
+    /// Gradient noise in [-1, 1]
+    fn noise(&self, p: Point3) -> f32 {
+        let base = p.floor();
+        let f = p - base;
+        // Hermite smoothing of the interpolation weights
+        let w = f * f * (Vec3::splat(3.0) - 2.0 * f);
+        let mask = PERLIN_POINT_COUNT - 1;
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[(base.x as i64 + di) as usize & mask]
+                        ^ self.perm_y[(base.y as i64 + dj) as usize & mask]
+                        ^ self.perm_z[(base.z as i64 + dk) as usize & mask];
+                    let offset = f - Vec3::new(di as f32, dj as f32, dk as f32);
+                    let weight = |d: i64, w: f32| if d == 1 { w } else { 1.0 - w };
+                    accum += weight(di, w.x) * weight(dj, w.y) * weight(dk, w.z) * self.gradients[index].dot(offset);
+                }
+            }
+        }
+        accum
+    }
+
+    /// Sum of depth octaves of noise, each at twice the frequency and half the weight
+    fn turbulence(&self, p: Point3, depth: u32) -> f32 {
+        let mut accum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+/// How UVs outside [0, 1] map onto an image
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Wrap {
+    #[serde(rename = "repeat")]
+    Repeat,
+    #[serde(rename = "clamp")]
+    Clamp,
+    #[serde(rename = "mirror")]
+    Mirror,
+}
+
+impl Wrap {
+    fn texel_index(self, i: i64, size: u32) -> usize {
+        let size = size as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Clamp => i.clamp(0, size - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size { i } else { 2 * size - 1 - i }
+            },
+        };
+        i as usize
+    }
+}
+
+/// Reference to an image in the scene, with how it wraps
+#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct ImageRef {
+    pub index: usize,
+    pub wrap: Wrap,
+}
+
+/// Image mapped onto surfaces by UV, with (0, 0) at the bottom left
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    /// Texture from linear values
+    pub fn new(image: &HdrImage) -> Self {
+        ImageTexture {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|p| Color::new(p[0], p[1], p[2])).collect(),
+        }
+    }
+
+    /// Texture from an 8-bit sRGB encoded image, such as a PNG
+    pub fn from_srgb(image: &image::RgbImage) -> Self {
+        let decode = |v: u8| {
+            let v = v as f32 / 255.0;
+            if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+        };
+        ImageTexture {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2]))).collect(),
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64, wrap: Wrap) -> Color {
+        let x = wrap.texel_index(x, self.width);
+        let y = wrap.texel_index(y, self.height);
+        self.pixels[y * self.width as usize + x]
+    }
+
+    /// Bilinearly filtered colour at u,v
+    pub fn value(&self, u: f32, v: f32, wrap: Wrap) -> Color {
+        // Texel centers are at half integer coordinates, and rows run from top to bottom
+        let px = u * self.width as f32 - 0.5;
+        let py = (1.0 - v) * self.height as f32 - 0.5;
+        let (x0, y0) = (px.floor(), py.floor());
+        let (fx, fy) = (px - x0, py - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0, wrap) * (1.0 - fx) + self.texel(x0 + 1, y0, wrap) * fx;
+        let bottom = self.texel(x0, y0 + 1, wrap) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1, wrap) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
@@ -0,0 +1,177 @@
+use serde::{Serialize, Deserialize};
+
+use crate::hdr::HdrImage;
+use crate::shared::Color;
+
+/// Curve used to compress linear radiance into the displayable range
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Operator {
+    #[serde(rename = "clamp")]
+    Clamp,
+    #[serde(rename = "reinhard")]
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    #[serde(rename = "aces")]
+    Aces,
+    // John Hable's Uncharted 2 filmic curve
+    #[serde(rename = "hable")]
+    Hable,
+}
+
+/// Transfer function used to encode the tone mapped values
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Oetf {
+    #[serde(rename = "gamma")]
+    Gamma,
+    #[serde(rename = "srgb")]
+    Srgb,
+}
+
+/// Settings for converting linear radiance to 8-bit output
+#[derive(Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToneMap {
+    pub operator: Operator,
+    // In stops, i.e. radiance is scaled by 2^exposure
+    pub exposure: f32,
+    pub oetf: Oetf,
+    // Only used with the gamma OETF
+    pub gamma: f32,
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        ToneMap {
+            operator: Operator::Clamp,
+            exposure: 0.0,
+            oetf: Oetf::Gamma,
+            gamma: 2.2,
+        }
+    }
+}
+
+fn hable_partial(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+impl Operator {
+    fn apply(&self, v: f32) -> f32 {
+        match self {
+            Operator::Clamp => v,
+            Operator::Reinhard => v / (1.0 + v),
+            Operator::Aces => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                (v * (a * v + b)) / (v * (c * v + d) + e)
+            },
+            Operator::Hable => {
+                // Exposure bias and linear white point from the original presentation
+                let white = 11.2;
+                hable_partial(v * 2.0) / hable_partial(white)
+            },
+        }
+    }
+}
+
+impl Oetf {
+    fn encode(&self, v: f32, gamma: f32) -> f32 {
+        match self {
+            Oetf::Gamma => v.powf(1.0 / gamma),
+            Oetf::Srgb => if v <= 0.0031308 {
+                12.92 * v
+            } else {
+                1.055 * v.powf(1.0 / 2.4) - 0.055
+            },
+        }
+    }
+}
+
+impl ToneMap {
+    /// Map a linear radiance value to an 8-bit display value
+    pub fn map(&self, c: Color) -> image::Rgb<u8> {
+        let scale = 2f32.powf(self.exposure);
+        let channel = |v: f32| {
+            // max also discards any NaNs
+            let mapped = self.operator.apply((v * scale).max(0.0)).min(1.0);
+            (255.0 * self.oetf.encode(mapped, self.gamma) + 0.5) as u8
+        };
+        image::Rgb([channel(c.x), channel(c.y), channel(c.z)])
+    }
+
+    /// Tone map a whole image
+    pub fn apply(&self, img: &HdrImage) -> image::RgbImage {
+        let mut ldr = image::RgbImage::new(img.width(), img.height());
+        for (x, y, pixel) in img.enumerate_pixels() {
+            ldr.put_pixel(x, y, self.map(Color::new(pixel[0], pixel[1], pixel[2])));
+        }
+        ldr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [Operator; 4] = [Operator::Clamp, Operator::Reinhard, Operator::Aces, Operator::Hable];
+
+    fn tonemap(operator: Operator, oetf: Oetf) -> ToneMap {
+        ToneMap { operator, oetf, ..ToneMap::default() }
+    }
+
+    #[test]
+    fn operator_curves() {
+        for operator in OPERATORS.iter() {
+            assert!(operator.apply(0.0).abs() < 1e-6);
+            // Brighter input is never darker
+            let mut last = 0.0;
+            for i in 1..200 {
+                let v = operator.apply(i as f32 * 0.1);
+                assert!(v >= last, "decreasing at {}", i as f32 * 0.1);
+                last = v;
+            }
+        }
+        assert_eq!(Operator::Clamp.apply(0.3), 0.3);
+        assert_eq!(Operator::Reinhard.apply(1.0), 0.5);
+        // ACES is close to 0.8 at 1, and Hable reaches white at half its white point
+        assert!((Operator::Aces.apply(1.0) - 0.8038).abs() < 1e-3);
+        assert!((Operator::Hable.apply(5.6) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn oetf_curves() {
+        assert_eq!(Oetf::Gamma.encode(1.0, 2.2), 1.0);
+        assert!((Oetf::Gamma.encode(0.5, 2.0) - 0.5f32.sqrt()).abs() < 1e-6);
+        assert_eq!(Oetf::Srgb.encode(0.0, 2.2), 0.0);
+        assert!((Oetf::Srgb.encode(1.0, 2.2) - 1.0).abs() < 1e-6);
+        // The linear and power segments meet
+        assert!((Oetf::Srgb.encode(0.0031308, 2.2) - 0.04045).abs() < 1e-5);
+        assert!((Oetf::Srgb.encode(0.0031309, 2.2) - 0.04045).abs() < 1e-5);
+    }
+
+    #[test]
+    fn map() {
+        let clamp = tonemap(Operator::Clamp, Oetf::Gamma);
+        assert_eq!(clamp.map(Color::new(0.0, 1.0, 5.0)), image::Rgb([0, 255, 255]));
+        assert_eq!(clamp.map(Color::new(0.5, -1.0, 0.0)), image::Rgb([186, 0, 0]));
+        assert_eq!(clamp.map(Color::new(f32::NAN, f32::INFINITY, f32::NEG_INFINITY)), image::Rgb([0, 255, 0]));
+        assert_eq!(tonemap(Operator::Clamp, Oetf::Srgb).map(Color::splat(0.5)), image::Rgb([188, 188, 188]));
+        // Each stop of exposure doubles the radiance
+        let brighter = ToneMap { exposure: 1.0, ..clamp };
+        assert_eq!(brighter.map(Color::splat(0.25)), clamp.map(Color::splat(0.5)));
+        for &operator in OPERATORS.iter() {
+            assert_eq!(tonemap(operator, Oetf::Srgb).map(Color::splat(1e6)), image::Rgb([255, 255, 255]));
+        }
+    }
+
+    #[test]
+    fn apply() {
+        let img = HdrImage::from_fn(3, 2, |x, y| image::Rgb([x as f32 / 2.0, y as f32, 0.0]));
+        let ldr = ToneMap::default().apply(&img);
+        assert_eq!(ldr.dimensions(), (3, 2));
+        assert_eq!(*ldr.get_pixel(2, 1), image::Rgb([255, 255, 0]));
+        assert_eq!(*ldr.get_pixel(1, 0), ToneMap::default().map(Color::new(0.5, 0.0, 0.0)));
+    }
+}